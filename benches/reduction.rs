@@ -0,0 +1,64 @@
+//! Compare flat reduction (every manager sends straight to the reporter)
+//! with tree reduction through aggregators of various fan-ins.
+//!
+//! Each "manager" here is just a thread that sends a fixed number of
+//! reports, so this measures the cost of getting reports to the root and
+//! nothing else. Run with `cargo bench --bench reduction`.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::unbounded;
+use pi_estimation_channels::{reduction::build_tree, report::Report};
+
+const REPORTS_PER_MANAGER: usize = 20_000;
+const NUM_RUNS: u32 = 5;
+
+fn reduce(num_managers: usize, fan_in: usize) -> Duration {
+    let (root_sender, root_receiver) = unbounded();
+    let (leaves, aggregators) = build_tree(&root_sender, num_managers, fan_in);
+    drop(root_sender);
+
+    let start = Instant::now();
+    let total = thread::scope(|s| {
+        for leaf in leaves {
+            s.spawn(move || {
+                for _ in 0..REPORTS_PER_MANAGER {
+                    leaf.send(Report::new(3, 4)).unwrap();
+                }
+            });
+        }
+        for a in aggregators {
+            s.spawn(move || a.run().unwrap());
+        }
+        root_receiver.iter().sum::<Report>()
+    });
+    let elapsed = start.elapsed();
+
+    assert_eq!(
+        total.total_num_points,
+        4 * REPORTS_PER_MANAGER * num_managers
+    );
+    elapsed
+}
+
+fn main() {
+    println!("| managers | fan-in | mean time |");
+    println!("|----------|--------|-----------|");
+    for num_managers in [4, 16, 64, 256] {
+        for fan_in in [0, 2, 4, 16] {
+            let total: Duration = (0..NUM_RUNS).map(|_| reduce(num_managers, fan_in)).sum();
+            let label = if fan_in == 0 {
+                "flat".to_string()
+            } else {
+                fan_in.to_string()
+            };
+            println!(
+                "| {num_managers:>8} | {label:>6} | {:>9.2?} |",
+                total / NUM_RUNS
+            );
+        }
+    }
+}
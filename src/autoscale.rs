@@ -0,0 +1,60 @@
+/// Decides when to add or remove managers, based on regular samples of
+/// how many points are waiting in the channel for them.
+///
+/// Points piling up means the managers can't keep up, so if the queue is
+/// past `high_water` and hasn't started shrinking we add one. A queue
+/// that stays empty means the managers are mostly waiting around, so
+/// after several empty samples in a row we retire one.
+#[derive(Debug)]
+pub struct Autoscaler {
+    high_water: usize,
+    max_managers: usize,
+    last_queued: usize,
+    empty_samples: usize,
+}
+
+/// What the [`Autoscaler`] thinks should happen to the managers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    Add,
+    Remove,
+    Hold,
+}
+
+/// How many samples in a row the queue has to be empty before we
+/// retire a manager. This is more than one so that a brief lull (or a
+/// manager we only just added draining the queue) doesn't immediately
+/// undo an addition.
+const EMPTY_SAMPLES_TO_REMOVE: usize = 5;
+
+impl Autoscaler {
+    pub fn new(high_water: usize, max_managers: usize) -> Self {
+        Self {
+            high_water,
+            max_managers,
+            last_queued: 0,
+            empty_samples: 0,
+        }
+    }
+
+    /// Take a sample of how many points are `queued`, with `managers`
+    /// currently running, and decide what to do.
+    pub fn sample(&mut self, queued: usize, managers: usize) -> Scaling {
+        let growing = queued >= self.last_queued;
+        self.last_queued = queued;
+        if queued == 0 {
+            self.empty_samples += 1;
+        } else {
+            self.empty_samples = 0;
+        }
+
+        if queued >= self.high_water && growing && managers < self.max_managers {
+            Scaling::Add
+        } else if self.empty_samples >= EMPTY_SAMPLES_TO_REMOVE && managers > 1 {
+            self.empty_samples = 0;
+            Scaling::Remove
+        } else {
+            Scaling::Hold
+        }
+    }
+}
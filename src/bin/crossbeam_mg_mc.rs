@@ -1,181 +1,1774 @@
 use clap::Parser;
-use crossbeam_channel::{Sender, unbounded};
-use pi_estimation_channels::{NUM_POINTS, calculate_estimate, point::Point};
+use crossbeam_channel::{
+    Receiver, RecvTimeoutError, SendTimeoutError, Sender, TryRecvError, TrySendError, at, bounded,
+    never, select, tick, unbounded,
+};
+use pi_estimation_channels::{
+    NUM_POINTS,
+    autoscale::{Autoscaler, Scaling},
+    cadence::{Cadence, Ticker},
+    channels::Link,
+    checkpoint::Checkpoint,
+    commands::{Command, read_commands},
+    control::{Control, ControlGroup},
+    display::{Dashboard, DisplayMode},
+    error::{Failures, PipelineError, join_stage},
+    estimators::Moments,
+    faults::{Action, Fault, FaultPlan, InjectFaults, Injection},
+    interrupt::interrupts,
+    latency::{Histogram, Stamped},
+    metrics::{StageMetrics, StageRegistry, utilization_table},
+    monitor::{Monitor, occupancy_table},
+    output::{OutputFormat, Record, RecordKind, write_record},
+    point::Point,
+    reduction::{Aggregator, build_tree},
+    replicates::Replicates,
+    report::Report,
+    results::{RunResult, merge},
+    rng::{PointRng, PointStream},
+    roster::{Member, Roster},
+    strata::{Allocation, Strata, StrataTally},
+    supervision::{CancelToken, RestartPolicy, Supervisor},
+    trace::write_chrome_trace,
+    watchdog::Watchdog,
+    work::WorkPool,
+};
 use rand::{Rng, rng};
-use std::{fmt::Debug, iter::repeat_with, thread};
+use std::{
+    fmt::Debug,
+    mem,
+    num::NonZeroUsize,
+    ops::Range,
+    path::PathBuf,
+    process::{self, ExitCode},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    subcommand: Option<Subcommand>,
     #[arg(short = 'g', long, default_value_t = 1)]
     num_generators: usize,
     #[arg(short = 'm', long, default_value_t = 1)]
     num_managers: usize,
+    /// The total number of points to generate [default: 10000000, or
+    /// the total the checkpoint was for when resuming].
+    #[arg(short = 'n', long)]
+    num_points: Option<usize>,
+    /// Generate the points from this seed, so that the same seed and
+    /// number of points always give the same estimate, however many
+    /// generators there are (except with `--rng xoshiro`).
+    #[arg(long)]
+    seed: Option<u64>,
+    /// How to generate the points [default: chacha12, or whatever the
+    /// checkpoint used when resuming]. With `philox` each point depends
+    /// only on the seed and its index, so a seeded run counts exactly the
+    /// same points however it's split up. With `xoshiro` each generator
+    /// gets its own substream of the seed's stream. `halton`, `sobol`,
+    /// and `r2` are quasi-random sequences given a random shift by the
+    /// seed, which give much better estimates than random points; use
+    /// `--replicates` to see how good.
+    #[arg(long, value_enum)]
+    rng: Option<PointRng>,
+    /// Run this many independent replicates of the whole run, the Nth
+    /// from seed `--seed` + N (or from fresh entropy if unseeded), and
+    /// print the estimate they give together, with its standard error
+    /// measured from how much they differ.
+    #[arg(long, default_value_t = 1, conflicts_with_all = ["checkpoint", "resume", "save"])]
+    replicates: usize,
+    /// Split the square into a grid of this many cells by this many, and
+    /// generate the same number of points in each, printing the
+    /// stratified estimate (and how much less variance it has than the
+    /// plain one) at the end. `-n` must be a multiple of the number of
+    /// cells.
+    #[arg(long, conflicts_with = "resume")]
+    strata: Option<usize>,
+    /// Also work out the antithetic and control variate estimates, and
+    /// print them at the end along with how much less variance each has
+    /// than the plain estimate.
+    #[arg(long, conflicts_with = "resume")]
+    variance_reduction: bool,
+    /// Sample points from the cube [-1, 1]^D rather than the square, and
+    /// estimate π from the volume of the unit ball in D dimensions (from 2
+    /// to 10) [default: 2, or whatever the checkpoint used when resuming].
+    /// The ball takes up less and less of the cube as D goes up, so the
+    /// estimate gets worse for the same number of points.
+    #[arg(long)]
+    dimensions: Option<usize>,
+    /// The index of the first point to generate [default: 0]. Seeded runs
+    /// on separate machines can use the same seed and still generate
+    /// different points by starting from different indices.
+    #[arg(long)]
+    first_point: Option<usize>,
+    /// The index of the first generator stream to use with `--rng
+    /// xoshiro` [default: 0]. Like `--first-point`, this lets separate
+    /// seeded runs generate different points.
+    #[arg(long)]
+    first_stream: Option<usize>,
+    /// The maximum number of report senders feeding into any one
+    /// aggregator or into the reporter. With the default of 0 every
+    /// manager sends straight to the reporter ("flat" reduction);
+    /// otherwise reports are merged through a tree of aggregators.
+    #[arg(short = 'f', long, default_value_t = 0)]
+    fan_in: usize,
+    /// How often each manager sends a report: a number of points
+    /// (e.g., 10000), a time (e.g., 50ms), or "adaptive".
+    #[arg(long, default_value_t = Cadence::Points(10_000))]
+    manager_cadence: Cadence,
+    /// How often the reporter updates the display, in the same format
+    /// as `--manager-cadence`. Reports that arrive between updates are
+    /// combined into a single update.
+    #[arg(long, default_value_t = Cadence::Interval(Duration::from_millis(250)))]
+    reporter_cadence: Cadence,
+    /// How to show progress. The dashboard is redrawn in place, so
+    /// `auto` only uses it when stdout is a terminal.
+    #[arg(long, value_enum, default_value_t = DisplayMode::Auto)]
+    display: DisplayMode,
+    /// The format for progress updates and the final summary on stdout.
+    /// Diagnostic messages always go to stderr.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Don't measure how long each stage spends blocked sending and
+    /// receiving, or print the table of per-stage utilization at the end
+    /// of the run. The timing adds noticeable overhead to every message.
+    #[arg(long)]
+    no_metrics: bool,
+    /// Sample how many messages are waiting in each channel every this
+    /// many milliseconds (at least 1), and print the min/mean/max for each
+    /// channel at the end of the run.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    monitor_ms: Option<u64>,
+    /// Give up on the run (after printing what each stage is doing) if no
+    /// stage makes any progress for this many milliseconds. 0 turns the
+    /// watchdog off.
+    #[arg(long, default_value_t = 10_000)]
+    watchdog_ms: u64,
+    /// Read commands from stdin while the run is going: `status`,
+    /// `pause`, `resume`, `flush` (to have the managers report what they
+    /// have so far), `add-generator`, `remove-generator`, `add-manager`,
+    /// `remove-manager`, or `stop` (to finish early with an estimate from
+    /// the points generated so far).
+    #[arg(long)]
+    interactive: bool,
+    /// Add managers while points are piling up in the points channel,
+    /// and remove them while it stays empty.
+    #[arg(long)]
+    autoscale: bool,
+    /// The most managers `--autoscale` will run at once. Defaults to the
+    /// number of CPUs.
+    #[arg(long)]
+    max_managers: Option<usize>,
+    /// Save a checkpoint of the run's progress to this file every
+    /// `--checkpoint-every`, and at the end of the run.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// How often to save a checkpoint, in the same format as
+    /// `--manager-cadence`.
+    #[arg(long, default_value_t = Cadence::Interval(Duration::from_secs(10)))]
+    checkpoint_every: Cadence,
+    /// Carry on from a checkpoint saved by `--checkpoint`, counting
+    /// points until the run's total reaches `-n`. The points already
+    /// counted aren't generated again. (To keep saving checkpoints, pass
+    /// `--checkpoint` too; it can be the same file.)
+    #[arg(long)]
+    resume: Option<PathBuf>,
+    /// When the run finishes (including when it's stopped early by
+    /// `stop` or Ctrl-C), save its results to this file, for combining
+    /// with other runs' results using `merge`.
+    #[arg(long)]
+    save: Option<PathBuf>,
+    /// Record what each thread is doing over time and write it to this
+    /// file in the Chrome Trace Event format (viewable in Perfetto or
+    /// `chrome://tracing`).
+    #[arg(long)]
+    trace: Option<PathBuf>,
+    /// Stamp each point with the time it was generated, and report
+    /// percentiles of how long points took to get from generation to
+    /// classification at the end of the run.
+    #[arg(long)]
+    latency: bool,
+    /// Use a bounded channel with this capacity between the generators
+    /// and the managers instead of an unbounded one.
+    #[arg(long)]
+    channel_capacity: Option<usize>,
+    /// What to do when a manager panics: `never` restart it (the run
+    /// fails), restart it up to some number of times, or `always`
+    /// restart it. A restarted manager picks up where it left off, so no
+    /// points are lost.
+    #[arg(long, default_value_t = RestartPolicy::Never)]
+    restart: RestartPolicy,
+    /// Make managers panic this many times in total, each time picking a
+    /// manager and a point in its run at random. This is for testing
+    /// `--restart`.
+    #[arg(long, default_value_t = 0)]
+    kill_random: usize,
+    /// Inject a fault into one or more stages, as `STAGE:FAULT[@N]`.
+    /// `STAGE` is a kind of stage (`generator`, `manager`, `aggregator`,
+    /// or `reporter`), optionally followed by a number to pick just one
+    /// (e.g., `manager1`). `FAULT` is `panic`, `stall=TIME` (e.g.,
+    /// `stall=500ms`), `drop` or `drop=N` to throw away messages, or
+    /// `close` to quietly stop early. `N` is the number of messages the
+    /// stage handles before the fault (0 if left out). Can be repeated.
+    #[arg(long)]
+    inject: Vec<Injection>,
 }
 
-struct MessageGenerator<T> {
-    num_messages: usize,
-    send_channel: Sender<T>,
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Subcommand {
+    /// Combine the results saved (with `--save`) by separate runs into a
+    /// single estimate.
+    Merge {
+        /// The result files to combine.
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// The format for the combined estimate on stdout.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
 }
 
-impl MessageGenerator<Point> {
-    pub fn new(num_messages: usize, send_channel: Sender<Point>) -> Self {
+/// How many points a manager handles between looks at the clock when
+/// its cadence is time based.
+const CLOCK_STRIDE: usize = 256;
+
+/// How many points make up one span of generating or classifying in a
+/// trace. One span per point would make for an enormous trace file.
+const TRACE_BATCH_SIZE: usize = 1_000;
+
+/// How often a generator stuck waiting on a full channel (or any stage
+/// that's paused) checks whether the run has been cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How often the autoscaler checks how many points are queued.
+const AUTOSCALE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How many queued points count as the managers falling behind, when the
+/// points channel is unbounded. (For a bounded channel it's half the
+/// capacity.)
+const AUTOSCALE_HIGH_WATER: usize = 10 * TRACE_BATCH_SIZE;
+
+/// How many points a manager handles between checks for control
+/// messages, as long as points keep coming. (It checks right away if
+/// it runs out of points.)
+const CONTROL_STRIDE: usize = 256;
+
+/// The most dimensions `--dimensions` can ask for. The pipeline is built
+/// separately for each number of dimensions, and in 10 the ball already
+/// takes up less than 1/400 of the cube.
+const MAX_DIMENSIONS: usize = 10;
+
+/// The points still to be generated, along with a roster of the
+/// generators sending them, which holds the sender for new generators
+/// to send on, and the streams (for `--rng xoshiro`) they generate from.
+///
+/// The roster is closed once the work has all been taken (or the run is
+/// stopped), so that the points channel closes as soon as the last
+/// generator is done.
+struct PointSource<const D: usize> {
+    work: WorkPool,
+    batch_size: usize,
+    /// The index of the next stream to hand out to a generator.
+    streams: AtomicUsize,
+    generators: Arc<Roster<Sender<Stamped<Point<D>>>>>,
+}
+
+impl<const D: usize> PointSource<D> {
+    fn new(
+        points: Range<usize>,
+        batch_size: usize,
+        first_stream: usize,
+        sender: Sender<Stamped<Point<D>>>,
+    ) -> Self {
         Self {
-            num_messages,
+            work: WorkPool::new(points),
+            batch_size,
+            streams: AtomicUsize::new(first_stream),
+            generators: Arc::new(Roster::new(sender)),
+        }
+    }
+
+    /// Take the next batch of points to generate.
+    fn take(&self) -> Option<Range<usize>> {
+        let batch = self.work.take(self.batch_size);
+        if batch.is_none() {
+            self.generators.close();
+        }
+        batch
+    }
+
+    /// Take a stream for a new generator to generate from.
+    fn take_stream(&self) -> usize {
+        self.streams.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Stop handing out batches, so generators finish once they're done
+    /// with the batch they're on.
+    fn stop(&self) {
+        self.work.stop();
+        self.generators.close();
+    }
+}
+
+/// How far a [`PointSource`] has got, whatever the number of dimensions
+/// of its points, which is all the checkpointer needs to know about it.
+trait Progress: Send + Sync {
+    /// See [`WorkPool::position`].
+    fn position(&self) -> usize;
+
+    /// The index of the next stream to be handed out. Every generator
+    /// so far has had a stream with a lower index.
+    fn next_stream(&self) -> usize;
+}
+
+impl<const D: usize> Progress for PointSource<D> {
+    fn position(&self) -> usize {
+        self.work.position()
+    }
+
+    fn next_stream(&self) -> usize {
+        self.streams.load(Ordering::Relaxed)
+    }
+}
+
+struct MessageGenerator<const D: usize> {
+    source: Arc<PointSource<D>>,
+    /// Our place on the source's roster of generators.
+    _member: Member<Sender<Stamped<Point<D>>>>,
+    send_channel: Sender<Stamped<Point<D>>>,
+    points: PointStream,
+    allocation: Option<Allocation>,
+    stamp: bool,
+    control: crossbeam_channel::Receiver<Control>,
+    cancel: CancelToken,
+    metrics: Arc<StageMetrics>,
+    faults: FaultPlan,
+}
+
+impl<const D: usize> MessageGenerator<D> {
+    /// `points` is where this generator's points come from, which is a
+    /// stream of its own so that no two generators repeat each other.
+    pub fn new(
+        source: Arc<PointSource<D>>,
+        member: Member<Sender<Stamped<Point<D>>>>,
+        send_channel: Sender<Stamped<Point<D>>>,
+        points: PointStream,
+        control: crossbeam_channel::Receiver<Control>,
+        cancel: CancelToken,
+        metrics: Arc<StageMetrics>,
+    ) -> Self {
+        Self {
+            source,
+            _member: member,
             send_channel,
+            points,
+            allocation: None,
+            stamp: false,
+            control,
+            cancel,
+            metrics,
+            faults: FaultPlan::default(),
         }
     }
 
-    pub fn send_messages(self) {
-        for _ in 0..self.num_messages {
-            let value = rng().random();
-            self.send_channel.send(value).unwrap();
+    /// Stamp each point with the time it was generated, if `stamp`.
+    pub fn with_stamps(mut self, stamp: bool) -> Self {
+        self.stamp = stamp;
+        self
+    }
+
+    /// Put each point in the cell `allocation` says it goes in, rather
+    /// than anywhere in the square.
+    pub fn with_allocation(mut self, allocation: Option<Allocation>) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// Generate and send batches of points until there are none left (or
+    /// we're told to shut down), returning how many we sent.
+    pub fn run(mut self) -> Result<usize, PipelineError> {
+        self.metrics.start();
+        let mut num_sent = 0;
+        loop {
+            self.cancel.check(self.metrics.name())?;
+            if !self.check_control()? {
+                break;
+            }
+            let Some(batch) = self.source.take() else {
+                break;
+            };
+            let batch_size = batch.len();
+            self.points.start_batch(batch.start);
+            let _span = self.metrics.span("generating a batch");
+            for index in batch {
+                let mut point = self.points.point(index);
+                if let Some(allocation) = &self.allocation {
+                    point = allocation.place(point, index);
+                }
+                let value = Stamped::new(point, self.stamp);
+                match self.faults.next(self.metrics.messages()) {
+                    Action::Handle => {}
+                    // A dropped or unsent point is still counted as sent
+                    // (this is meant to look like a bug, after all).
+                    Action::Drop => continue,
+                    Action::Close => {
+                        self.metrics.finish();
+                        return Ok(num_sent + batch_size);
+                    }
+                }
+                self.metrics.time_send(|| self.send(value))?;
+                self.metrics.add_messages(1);
+            }
+            num_sent += batch_size;
         }
+        self.metrics.finish();
 
         // It's vital to drop the `send_channel` as a way of telling
         // the other end that we're done and that no more messages
         // will be coming from us. Without that the channel will never
         // close and the receiving channel will never terminate.
         drop(self.send_channel);
-        println!("Done sending messages");
+        eprintln!("Done sending messages");
+        Ok(num_sent)
+    }
+
+    /// Handle any control messages that have come in, returning whether
+    /// we should keep generating.
+    fn check_control(&self) -> Result<bool, PipelineError> {
+        while let Ok(control) = self.control.try_recv() {
+            match control {
+                Control::Pause => {
+                    if wait_for_resume(&self.control, &self.cancel, &self.metrics, || Ok(()))?
+                        != Control::Resume
+                    {
+                        return Ok(false);
+                    }
+                }
+                // Any batch we haven't taken yet is left for the other
+                // generators, so retiring is just stopping.
+                Control::Shutdown | Control::Retire => return Ok(false),
+                // We don't hold on to anything, so there's nothing to flush.
+                Control::Flush | Control::Resume => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Send `value`, giving up if the run is cancelled while we're
+    /// waiting for room in the channel. If the managers have all stopped
+    /// because of some failure then no one is ever going to make room, and
+    /// the channel doesn't disconnect while the display is still watching
+    /// it, so we can't just block in `send`.
+    fn send(&self, value: Stamped<Point<D>>) -> Result<(), PipelineError> {
+        let disconnected = || PipelineError::disconnected(self.metrics.name(), "points");
+        let mut value = match self.send_channel.try_send(value) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(value)) => value,
+            Err(TrySendError::Disconnected(_)) => return Err(disconnected()),
+        };
+        loop {
+            match self.send_channel.send_timeout(value, CANCEL_POLL_INTERVAL) {
+                Ok(()) => return Ok(()),
+                Err(SendTimeoutError::Timeout(unsent)) => {
+                    self.cancel.check(self.metrics.name())?;
+                    value = unsent;
+                }
+                Err(SendTimeoutError::Disconnected(_)) => return Err(disconnected()),
+            }
+        }
+    }
+}
+
+impl<const D: usize> InjectFaults for MessageGenerator<D> {
+    fn faults_mut(&mut self) -> &mut FaultPlan {
+        &mut self.faults
     }
 }
 
-struct PointManager {
+struct PointManager<const D: usize> {
+    cadence: Cadence,
     report_sender: Sender<Report>,
-    point_receiver: crossbeam_channel::Receiver<Point>,
+    point_receiver: Arc<crossbeam_channel::Receiver<Stamped<Point<D>>>>,
+    control: crossbeam_channel::Receiver<Control>,
+    since_control_check: usize,
+    /// Whether we've been told to `Retire`.
+    retired: bool,
+    /// Our place on the roster of managers, which keeps the reduction
+    /// tree open for managers added later.
+    _member: Option<Member<Vec<Sender<Report>>>>,
+    cancel: CancelToken,
+    metrics: Arc<StageMetrics>,
+    /// The counts for the points we've classified but not yet reported,
+    /// and the latencies (and cells, if the points are stratified, and
+    /// moments, if we're asked for them) of all the points we've
+    /// classified. These live here rather than in `run` so they survive a
+    /// restart.
+    pending: Report,
+    latencies: Histogram,
+    strata: Option<StrataTally>,
+    moments: Option<Moments>,
+    faults: FaultPlan,
 }
 
-impl PointManager {
+impl<const D: usize> PointManager<D> {
     pub fn new(
+        cadence: Cadence,
         report_sender: Sender<Report>,
-        point_receiver: crossbeam_channel::Receiver<Point>,
+        point_receiver: Arc<crossbeam_channel::Receiver<Stamped<Point<D>>>>,
+        control: crossbeam_channel::Receiver<Control>,
+        cancel: CancelToken,
+        metrics: Arc<StageMetrics>,
     ) -> Self {
         Self {
+            cadence,
             report_sender,
             point_receiver,
+            control,
+            // Check right away, in case we've joined a paused run.
+            since_control_check: CONTROL_STRIDE,
+            retired: false,
+            _member: None,
+            cancel,
+            metrics,
+            pending: Report::default(),
+            latencies: Histogram::new(),
+            strata: None,
+            moments: None,
+            faults: FaultPlan::default(),
         }
     }
 
-    pub fn receive_stuff(self) {
-        let mut num_inside = 0;
-        let mut total_points = 0;
+    /// Keep count of how many points land in each of `strata`.
+    pub fn with_strata(mut self, strata: Option<Strata>) -> Self {
+        self.strata = strata.map(StrataTally::new);
+        self
+    }
+
+    /// Keep the sums the variance reduction estimators need, if `keep`.
+    pub fn with_moments(mut self, keep: bool) -> Self {
+        self.moments = keep.then(Moments::default);
+        self
+    }
+
+    /// Hold on to a place on a roster for as long as we're around.
+    pub fn with_member(mut self, member: Member<Vec<Sender<Report>>>) -> Self {
+        self._member = Some(member);
+        self
+    }
+
+    /// Classify points until the generators are all done (or we're told
+    /// to retire), returning how long the points we got took to get here
+    /// (if they were stamped), which cells they were in (if they were
+    /// stratified), and their moments (if we kept them).
+    ///
+    /// If this panics it can be called again to carry on where it left
+    /// off: it only ever panics between points, when everything it has
+    /// taken off the channel is accounted for in `pending`.
+    pub fn run(&mut self) -> Result<Classified, PipelineError> {
+        let mut ticker = Ticker::new(self.cadence).with_clock_stride(CLOCK_STRIDE);
+
+        self.metrics.start();
+        // The spans borrow the metrics, so they need their own handle on
+        // them while we call methods on `self`.
+        let metrics = Arc::clone(&self.metrics);
+        let mut batch_span = metrics.span("classifying a batch");
+        let mut batch_size = 0;
+        loop {
+            // Faults are injected before we take the next point off the
+            // channel, so that an injected panic never loses a point.
+            let action = self.faults.next(self.metrics.messages());
+            if action == Action::Close {
+                self.metrics.finish();
+                return Ok(self.classified());
+            }
+            let Some(stamped) = self.next_point(&mut ticker)? else {
+                break;
+            };
+            self.cancel.check(self.metrics.name())?;
+            if action == Action::Drop {
+                continue;
+            }
+            if let Some(created) = stamped.created {
+                self.latencies.record(created.elapsed());
+            }
+            let inside = stamped.value.inside_unit_ball();
+            if inside {
+                self.pending.num_inside_points += 1;
+            }
+            if let Some(strata) = &mut self.strata {
+                strata.record(&stamped.value, inside);
+            }
+            if let Some(moments) = &mut self.moments {
+                moments.record(&stamped.value, inside);
+            }
+            self.pending.total_num_points += 1;
+            self.metrics.add_messages(1);
 
-        while let Ok(point) = self.point_receiver.recv() {
-            if point.inside_unit_circle() {
-                num_inside += 1;
+            ticker.record(1);
+            if ticker.is_due() {
+                self.flush(&mut ticker)?;
             }
-            total_points += 1;
 
-            if total_points % 10_000 == 0 {
-                self.report_sender
-                    .send(Report::new(num_inside, total_points))
-                    .unwrap();
-                num_inside = 0;
-                total_points = 0;
+            batch_size += 1;
+            if batch_size == TRACE_BATCH_SIZE {
+                drop(batch_span);
+                batch_span = metrics.span("classifying a batch");
+                batch_size = 0;
             }
         }
+        drop(batch_span);
 
-        println!("Done reading from generator.");
+        eprintln!("Done reading from generator.");
 
-        if total_points > 0 {
-            self.report_sender
-                .send(Report::new(num_inside, total_points))
-                .unwrap();
+        if !self.pending.is_empty() {
+            let report = mem::take(&mut self.pending);
+            self.send_report(report)?;
         }
+        self.metrics.finish();
 
-        println!("Done receiving messages & classifying points");
+        eprintln!("Done receiving messages & classifying points");
+        Ok(self.classified())
+    }
+
+    fn classified(&mut self) -> Classified {
+        Classified {
+            latencies: mem::take(&mut self.latencies),
+            strata: self.strata.take(),
+            moments: self.moments.take(),
+        }
+    }
+
+    /// Take the next point off the channel, handling any control
+    /// messages that come in first (or while we wait). `None` means the
+    /// generators are all done, or we've retired.
+    fn next_point(
+        &mut self,
+        ticker: &mut Ticker,
+    ) -> Result<Option<Stamped<Point<D>>>, PipelineError> {
+        self.since_control_check += 1;
+        if self.since_control_check >= CONTROL_STRIDE {
+            self.since_control_check = 0;
+            while let Ok(control) = self.control.try_recv() {
+                self.handle_control(control, ticker)?;
+            }
+        }
+        if self.retired {
+            return Ok(None);
+        }
+
+        // Most of the time there's a point waiting, and just taking it is
+        // a lot cheaper than a `select!`.
+        match self.point_receiver.try_recv() {
+            Ok(point) => return Ok(Some(point)),
+            Err(TryRecvError::Disconnected) => return Ok(None),
+            Err(TryRecvError::Empty) => {}
+        }
+        loop {
+            let received = self.metrics.time_recv(|| {
+                select! {
+                    recv(self.point_receiver) -> point => Ok(point.ok()),
+                    recv(self.control) -> control => Err(control.ok()),
+                }
+            });
+            match received {
+                Ok(point) => return Ok(point),
+                Err(Some(control)) => {
+                    self.handle_control(control, ticker)?;
+                    if self.retired {
+                        return Ok(None);
+                    }
+                }
+                // No one's left to send us control messages.
+                Err(None) => self.control = never(),
+            }
+        }
+    }
+
+    fn handle_control(
+        &mut self,
+        control: Control,
+        ticker: &mut Ticker,
+    ) -> Result<(), PipelineError> {
+        match control {
+            Control::Flush => self.flush(ticker),
+            // Flush before pausing so the reporter is up to date while
+            // we're paused.
+            Control::Pause => {
+                self.flush(ticker)?;
+                let (control, cancel, metrics) = (
+                    self.control.clone(),
+                    self.cancel.clone(),
+                    Arc::clone(&self.metrics),
+                );
+                if wait_for_resume(&control, &cancel, &metrics, || self.flush(ticker))?
+                    == Control::Retire
+                {
+                    self.retired = true;
+                }
+                Ok(())
+            }
+            // Whatever we have left over gets sent when `run` finishes.
+            Control::Retire => {
+                self.retired = true;
+                Ok(())
+            }
+            // When the run is shutting down we carry on until the
+            // generators are done, so that nothing they sent is lost.
+            Control::Resume | Control::Shutdown => Ok(()),
+        }
+    }
+
+    /// Send off the counts for the points we've classified since our
+    /// last report, if there are any.
+    fn flush(&mut self, ticker: &mut Ticker) -> Result<(), PipelineError> {
+        if !self.pending.is_empty() {
+            let _span = self.metrics.span("sending a report");
+            let report = mem::take(&mut self.pending);
+            self.send_report(report)?;
+        }
+        ticker.reset();
+        Ok(())
+    }
+
+    fn send_report(&self, report: Report) -> Result<(), PipelineError> {
+        self.metrics
+            .time_send(|| self.report_sender.send(report))
+            .map_err(|_| PipelineError::disconnected(self.metrics.name(), "reports"))
+    }
+}
+
+impl<const D: usize> InjectFaults for PointManager<D> {
+    fn faults_mut(&mut self) -> &mut FaultPlan {
+        &mut self.faults
     }
 }
 
-#[derive(Debug)]
-struct Report {
-    num_inside_points: usize,
-    total_num_points: usize,
+/// What a manager knows about the points it classified, beyond the
+/// counts it reported.
+struct Classified {
+    latencies: Histogram,
+    strata: Option<StrataTally>,
+    moments: Option<Moments>,
 }
 
-impl Report {
-    pub fn new(num_inside_points: usize, total_num_points: usize) -> Self {
-        Report {
-            num_inside_points,
-            total_num_points,
+/// Block after being told to `Pause` until we're told to `Resume`,
+/// `Shutdown`, or `Retire`, returning which it was. Any `Flush` that comes in while
+/// we're paused is handled by `flush`.
+fn wait_for_resume(
+    control: &crossbeam_channel::Receiver<Control>,
+    cancel: &CancelToken,
+    metrics: &StageMetrics,
+    mut flush: impl FnMut() -> Result<(), PipelineError>,
+) -> Result<Control, PipelineError> {
+    metrics.set_paused(true);
+    let resumed = loop {
+        match control.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(control @ (Control::Resume | Control::Shutdown | Control::Retire)) => break control,
+            Ok(Control::Flush) => flush()?,
+            Ok(Control::Pause) => {}
+            Err(RecvTimeoutError::Timeout) => cancel.check(metrics.name())?,
+            // No one's left to resume us, so we might as well carry on.
+            Err(RecvTimeoutError::Disconnected) => break Control::Resume,
         }
+    };
+    metrics.set_paused(false);
+    Ok(resumed)
+}
+
+/// Where and how often the reporter saves checkpoints, along with what
+/// it needs to know to write them.
+struct Checkpointer {
+    path: PathBuf,
+    ticker: Ticker,
+    requested_points: usize,
+    first_point: usize,
+    first_stream: usize,
+    rng: PointRng,
+    dimensions: usize,
+    seed: Option<u64>,
+    source: Arc<dyn Progress>,
+}
+
+impl Checkpointer {
+    fn save(&mut self, counted: Report) {
+        // The position has to be read after the counts were, so that it's
+        // past every point they include.
+        let checkpoint = Checkpoint {
+            requested_points: self.requested_points,
+            counted,
+            first_point: self.first_point,
+            position: self.source.position(),
+            first_stream: self.first_stream,
+            next_stream: self.source.next_stream(),
+            rng: self.rng,
+            dimensions: self.dimensions,
+            seed: self.seed,
+        };
+        if let Err(e) = checkpoint.save(&self.path) {
+            eprintln!(
+                "Failed to save a checkpoint to {}: {e}",
+                self.path.display()
+            );
+        }
+        self.ticker.reset();
     }
 }
 
 struct Reporter {
+    cadence: Cadence,
+    /// The counts from before this run, if it's resuming from a
+    /// checkpoint.
+    counted: Report,
     sender: Sender<Report>,
     receiver: crossbeam_channel::Receiver<Report>,
+    failure_sender: Sender<PipelineError>,
+    failure_receiver: crossbeam_channel::Receiver<PipelineError>,
+    generator_controls: ControlGroup,
+    manager_controls: ControlGroup,
+    commands: crossbeam_channel::Receiver<Command>,
+    checkpoints: Option<Checkpointer>,
+    /// Where to pass on the commands that change which stages are
+    /// running, which only the main thread can do.
+    topology_commands: Option<Sender<Command>>,
+    metrics: Arc<StageMetrics>,
+    faults: FaultPlan,
+}
+
+/// The things that can wake the reporter up.
+enum ReporterEvent {
+    Report(Report),
+    Failure(PipelineError),
+    FailuresClosed,
+    Command(Command),
+    CommandsClosed,
+    Tick,
+    Done,
 }
 
 impl Reporter {
-    pub fn new() -> Self {
+    pub fn new(cadence: Cadence) -> Self {
         let (sender, receiver) = unbounded();
-        Self { sender, receiver }
+        let (failure_sender, failure_receiver) = unbounded();
+        Self {
+            cadence,
+            counted: Report::default(),
+            sender,
+            receiver,
+            failure_sender,
+            failure_receiver,
+            generator_controls: ControlGroup::new(),
+            manager_controls: ControlGroup::new(),
+            commands: never(),
+            checkpoints: None,
+            topology_commands: None,
+            metrics: Arc::new(StageMetrics::new("reporter", "reporter").with_input("reports")),
+            faults: FaultPlan::default(),
+        }
+    }
+
+    /// The control channels to the generators and managers, so we can
+    /// pause them, or ask the managers for their counts when we need them.
+    pub fn with_controls(
+        mut self,
+        generator_controls: ControlGroup,
+        manager_controls: ControlGroup,
+    ) -> Self {
+        self.generator_controls = generator_controls;
+        self.manager_controls = manager_controls;
+        self
+    }
+
+    /// Take commands from an operator on `commands`, passing the ones
+    /// that add, remove, or stop generators on to `topology_commands`.
+    pub fn with_commands(
+        mut self,
+        commands: crossbeam_channel::Receiver<Command>,
+        topology_commands: Sender<Command>,
+    ) -> Self {
+        self.commands = commands;
+        self.topology_commands = Some(topology_commands);
+        self
+    }
+
+    /// Start from the counts of an earlier run.
+    pub fn with_counted(mut self, counted: Report) -> Self {
+        self.counted = counted;
+        self
+    }
+
+    /// Save checkpoints as we go.
+    pub fn with_checkpoints(mut self, checkpoints: Option<Checkpointer>) -> Self {
+        self.checkpoints = checkpoints;
+        self
     }
 
     pub fn get_sender(&self) -> Sender<Report> {
         self.sender.clone()
     }
 
-    pub fn report_stuff(self) {
-        let mut num_inside_points = 0;
-        let mut total_num_points = 0;
+    /// A sender for stages (well, their supervisor) to tell us that
+    /// they've failed.
+    pub fn get_failure_sender(&self) -> Sender<PipelineError> {
+        self.failure_sender.clone()
+    }
+
+    pub fn get_receiver(&self) -> crossbeam_channel::Receiver<Report> {
+        self.receiver.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Collect reports as they come in, but only update the display
+    /// when our cadence says it's time, so a flood of small reports gets
+    /// coalesced into a single update.
+    ///
+    /// Returns the combined report of all the points that were counted,
+    /// or the first failure we heard about, in which case the run is
+    /// invalid and we don't report a final estimate.
+    pub fn run(mut self, mut dashboard: Dashboard) -> Result<Report, PipelineError> {
+        let mut total = self.counted;
+        let mut ticker = Ticker::new(self.cadence);
+        let mut checkpoints = self.checkpoints;
+        let mut num_displayed = 0;
+        let mut failure = None;
+        let mut failure_receiver = self.failure_receiver;
+        let mut commands = self.commands;
 
         drop(self.sender);
+        drop(self.failure_sender);
 
-        while let Ok(report) = self.receiver.recv() {
-            num_inside_points += report.num_inside_points;
-            total_num_points += report.total_num_points;
-            let estimate = calculate_estimate(num_inside_points, total_num_points);
-            println!(
-                "After {} points the estimate is {estimate}.",
-                total_num_points,
-            );
+        self.metrics.start();
+        loop {
+            let deadline = [
+                ticker.deadline(),
+                checkpoints.as_ref().and_then(|c| c.ticker.deadline()),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let timer = deadline.map_or_else(never, at);
+            let event = self.metrics.time_recv(|| {
+                select! {
+                    recv(self.receiver) -> report => {
+                        report.map_or(ReporterEvent::Done, ReporterEvent::Report)
+                    }
+                    recv(failure_receiver) -> error => {
+                        error.map_or(ReporterEvent::FailuresClosed, ReporterEvent::Failure)
+                    }
+                    recv(commands) -> command => {
+                        command.map_or(ReporterEvent::CommandsClosed, ReporterEvent::Command)
+                    }
+                    recv(timer) -> _ => ReporterEvent::Tick,
+                }
+            });
+            match event {
+                ReporterEvent::Report(report) => {
+                    match self.faults.next(self.metrics.messages()) {
+                        Action::Handle => {}
+                        Action::Drop => continue,
+                        Action::Close => break,
+                    }
+                    let _span = self.metrics.span("merging a report");
+                    self.metrics.add_messages(1);
+                    total += report;
+                    ticker.record(report.total_num_points);
+                    if let Some(checkpoints) = &mut checkpoints {
+                        checkpoints.ticker.record(report.total_num_points);
+                    }
+                }
+                ReporterEvent::Failure(error) => {
+                    // We keep going after a failure (everyone else should
+                    // be stopping anyway), but we only remember the first.
+                    eprintln!("Failure: {error}");
+                    failure.get_or_insert(error);
+                }
+                // Once every stage's supervisor is gone, don't keep
+                // selecting on a channel that will never have anything.
+                ReporterEvent::FailuresClosed => failure_receiver = never(),
+                ReporterEvent::Command(command) => match command {
+                    // The managers' latest counts won't have arrived yet,
+                    // so this shows what we had as of their last reports,
+                    // but it asks for fresh ones for the next update.
+                    Command::Status => {
+                        self.manager_controls.send(Control::Flush);
+                        dashboard.status(&total);
+                    }
+                    Command::Pause => {
+                        self.generator_controls.send(Control::Pause);
+                        self.manager_controls.send(Control::Pause);
+                        eprintln!("Sent {command}.");
+                    }
+                    Command::Resume => {
+                        self.generator_controls.send(Control::Resume);
+                        self.manager_controls.send(Control::Resume);
+                        eprintln!("Sent {command}.");
+                    }
+                    Command::Flush => {
+                        self.manager_controls.send(Control::Flush);
+                        eprintln!("Sent {command}.");
+                    }
+                    Command::AddGenerator
+                    | Command::RemoveGenerator
+                    | Command::AddManager
+                    | Command::RemoveManager
+                    | Command::Stop => {
+                        if let Some(topology_commands) = &self.topology_commands {
+                            let _ = topology_commands.send(command);
+                        }
+                    }
+                },
+                ReporterEvent::CommandsClosed => commands = never(),
+                ReporterEvent::Tick => {}
+                ReporterEvent::Done => {
+                    // A stage that fails reports it before it drops its
+                    // report sender, but `select!` picks at random among
+                    // the channels that are ready, so check for a failure
+                    // that hasn't been picked up yet.
+                    if let Ok(error) = failure_receiver.try_recv() {
+                        eprintln!("Failure: {error}");
+                        failure.get_or_insert(error);
+                    }
+                    break;
+                }
+            }
+
+            if failure.is_none()
+                && let Some(checkpoints) = &mut checkpoints
+                && checkpoints.ticker.is_due()
+            {
+                let _span = self.metrics.span("saving a checkpoint");
+                checkpoints.save(total);
+            }
+
+            if failure.is_none() && ticker.is_due() {
+                if total.total_num_points > num_displayed {
+                    let _span = self.metrics.span("updating the display");
+                    dashboard.update(&total);
+                    num_displayed = total.total_num_points;
+                } else {
+                    // Nothing new has come in since the last update (e.g.,
+                    // the managers report less often than we update), so
+                    // ask them for what they have so far.
+                    self.manager_controls.send(Control::Flush);
+                }
+                ticker.reset();
+            }
+        }
+
+        if let Some(error) = failure {
+            dashboard.abort(&total, &error);
+            self.metrics.finish();
+            return Err(error);
+        }
+
+        // Always finish with the final estimate, even if it isn't "time"
+        // for an update yet.
+        if total.total_num_points > num_displayed {
+            dashboard.update(&total);
+        }
+        dashboard.finish(&total);
+        if let Some(checkpoints) = &mut checkpoints {
+            checkpoints.save(total);
         }
+        self.metrics.finish();
 
-        println!("Done generating reports");
+        eprintln!("Done generating reports");
+        Ok(total)
     }
 }
 
-fn main() {
+impl InjectFaults for Reporter {
+    fn faults_mut(&mut self) -> &mut FaultPlan {
+        &mut self.faults
+    }
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
+    let result = match &args.subcommand {
+        Some(Subcommand::Merge { files, format }) => merge_results(files, *format),
+        None => {
+            let interrupts = interrupts();
+            if args.replicates == 1 {
+                run(&args, &interrupts).map(drop)
+            } else {
+                run_replicates(&args, &interrupts)
+            }
+        }
+    };
+    match result {
+        Ok(()) => {
+            eprintln!("All done!");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    }
+}
 
-    let reporter = Reporter::new();
+/// Run the pipeline once, returning everything it counted (including
+/// anything counted before it was resumed).
+fn run(args: &Args, interrupts: &Receiver<()>) -> Result<Report, PipelineError> {
+    if args.num_generators == 0 || args.num_managers == 0 {
+        return Err(PipelineError::ConfigInvalid(
+            "there must be at least one generator and one manager".to_string(),
+        ));
+    }
 
-    let (generator_sender, managers_receiver) = unbounded();
+    let resumed = args
+        .resume
+        .as_deref()
+        .map(Checkpoint::load)
+        .transpose()
+        .map_err(PipelineError::ConfigInvalid)?;
+    let dimensions = match (args.dimensions, resumed.map(|c| c.dimensions)) {
+        (Some(dimensions), Some(resumed_dimensions)) if dimensions != resumed_dimensions => {
+            return Err(PipelineError::ConfigInvalid(format!(
+                "the checkpoint is for a run in {resumed_dimensions} dimensions, not {dimensions}"
+            )));
+        }
+        (dimensions, resumed_dimensions) => dimensions.or(resumed_dimensions).unwrap_or(2),
+    };
+    match dimensions {
+        2 => run_in::<2>(args, resumed, interrupts),
+        3 => run_in::<3>(args, resumed, interrupts),
+        4 => run_in::<4>(args, resumed, interrupts),
+        5 => run_in::<5>(args, resumed, interrupts),
+        6 => run_in::<6>(args, resumed, interrupts),
+        7 => run_in::<7>(args, resumed, interrupts),
+        8 => run_in::<8>(args, resumed, interrupts),
+        9 => run_in::<9>(args, resumed, interrupts),
+        10 => run_in::<10>(args, resumed, interrupts),
+        _ => Err(PipelineError::ConfigInvalid(format!(
+            "points can have from 2 to {MAX_DIMENSIONS} dimensions, not {dimensions}"
+        ))),
+    }
+}
 
-    let managers =
-        repeat_with(|| PointManager::new(reporter.get_sender(), managers_receiver.clone()))
-            .take(args.num_managers)
-            .collect::<Vec<_>>();
+/// Run the pipeline once with points in `D` dimensions, carrying on from
+/// the `resumed` checkpoint if there is one.
+fn run_in<const D: usize>(
+    args: &Args,
+    resumed: Option<Checkpoint>,
+    interrupts: &Receiver<()>,
+) -> Result<Report, PipelineError> {
+    let num_points = args
+        .num_points
+        .or(resumed.map(|c| c.requested_points))
+        .unwrap_or(NUM_POINTS);
+    let first_point = match (args.first_point, resumed.map(|c| c.first_point)) {
+        (Some(first), Some(resumed_first)) if first != resumed_first => {
+            return Err(PipelineError::ConfigInvalid(format!(
+                "the checkpoint is for a run starting from point {resumed_first}, not {first}"
+            )));
+        }
+        (first, resumed_first) => first.or(resumed_first).unwrap_or(0),
+    };
+    let point_rng = match (args.rng, resumed.map(|c| c.rng)) {
+        (Some(point_rng), Some(resumed_rng)) if point_rng != resumed_rng => {
+            return Err(PipelineError::ConfigInvalid(format!(
+                "the checkpoint is for a run using {resumed_rng}, not {point_rng}"
+            )));
+        }
+        (point_rng, resumed_rng) => point_rng.or(resumed_rng).unwrap_or_default(),
+    };
+    if !point_rng.supports_dimensions(D) {
+        return Err(PipelineError::ConfigInvalid(format!(
+            "{point_rng} points only come in two dimensions, not {D}"
+        )));
+    }
+    let first_stream = match (args.first_stream, resumed.map(|c| c.first_stream)) {
+        (Some(first), Some(resumed_first)) if first != resumed_first => {
+            return Err(PipelineError::ConfigInvalid(format!(
+                "the checkpoint is for a run starting from stream {resumed_first}, not {first}"
+            )));
+        }
+        (first, resumed_first) => first.or(resumed_first).unwrap_or(0),
+    };
+    let seed = match (args.seed, resumed.and_then(|c| c.seed)) {
+        (Some(seed), Some(resumed_seed)) if seed != resumed_seed => {
+            return Err(PipelineError::ConfigInvalid(format!(
+                "the checkpoint is for a run seeded with {resumed_seed}, not {seed}"
+            )));
+        }
+        (seed, resumed_seed) => seed.or(resumed_seed),
+    };
+    // Unseeded runs still generate all their points from one seed, just
+    // one no one knows.
+    let point_seed = seed.unwrap_or_else(|| rng().random());
+    // A resumed run only generates the points that are still needed,
+    // starting from where the checkpointed run left off.
+    let counted = resumed.map_or_else(Report::default, |c| c.counted);
+    let start = resumed.map_or(first_point, |c| c.position);
+    let points = start..start + num_points.saturating_sub(counted.total_num_points);
+    let start_stream = resumed.map_or(first_stream, |c| c.next_stream);
+    let allocation = args
+        .strata
+        .map(|side| Allocation::new(Strata::new(side), first_point, num_points))
+        .transpose()
+        .map_err(PipelineError::ConfigInvalid)?;
 
-    let generators = repeat_with(|| {
-        MessageGenerator::new(NUM_POINTS / args.num_generators, generator_sender.clone())
-    })
-    .take(args.num_generators)
-    .collect::<Vec<_>>();
+    // `--kill-random` is just shorthand for injecting panics into random
+    // managers at random points in their runs.
+    let mut injections = args.inject.clone();
+    for _ in 0..args.kill_random {
+        injections.push(Injection::new(
+            "manager",
+            Some(rng().random_range(0..args.num_managers)),
+            Fault::Panic,
+            rng().random_range(0..=points.len() / args.num_managers),
+        ));
+    }
+
+    let reporter = Reporter::new(args.reporter_cadence);
+    let faults = FaultPlan::new(&reporter.metrics(), &injections);
+    let reporter = reporter.with_faults(faults);
+    let supervisor = Supervisor::new(reporter.get_failure_sender(), CancelToken::new());
+
+    let (generator_sender, managers_receiver) = match args.channel_capacity {
+        Some(capacity) => bounded(capacity),
+        None => unbounded(),
+    };
+    // The managers share the receiver through an `Arc` so that the link
+    // we watch it through doesn't keep the channel open.
+    let managers_receiver = Arc::new(managers_receiver);
 
-    // It is crucial to drop these clones of the sender and receiver
-    // used to communicate between the generators and the managers
-    // so that the channel can be properly closed when the generators
-    // are finished adding points to the channel.
+    // Each of the first managers gets its own sender into the reduction
+    // tree, and those added later share them. With flat reduction these
+    // are all just clones of the reporter's sender.
+    let (report_senders, aggregators) =
+        build_tree(&reporter.get_sender(), args.num_managers, args.fan_in);
+    let aggregators = aggregators
+        .into_iter()
+        .map(|a| {
+            let faults = FaultPlan::new(&a.metrics(), &injections);
+            a.with_faults(faults)
+        })
+        .collect::<Vec<_>>();
+
+    let registry = StageRegistry::new();
+    let register = |stage: Arc<StageMetrics>| {
+        stage.set_timing(!args.no_metrics);
+        stage.set_tracing(args.trace.is_some());
+        registry.register(stage);
+    };
+
+    // Generators take their points from a shared pool a batch at a time,
+    // so they can be added and removed during the run. The sender for
+    // the points channel lives in the pool until the work runs out.
+    // Stratified points are handed out a whole number of cells at a time
+    // (or part of one, for cells bigger than a batch), so each cell is
+    // generated by as few generators as possible.
+    let batch_size = allocation.map_or(TRACE_BATCH_SIZE, |a| a.batch_size(TRACE_BATCH_SIZE));
+    let source = Arc::new(PointSource::new(
+        points,
+        batch_size,
+        start_stream,
+        generator_sender,
+    ));
+    let generator_controls = ControlGroup::new();
+    let new_generator = |i: usize| {
+        let (member, send_channel) = source.generators.enlist()?;
+        let metrics = Arc::new(
+            StageMetrics::new("generator", format!("generator {i}")).with_output("points"),
+        );
+        register(Arc::clone(&metrics));
+        let faults = FaultPlan::new(&metrics, &injections);
+        let points = point_rng.stream(point_seed, source.take_stream());
+        let generator = MessageGenerator::new(
+            Arc::clone(&source),
+            member,
+            send_channel,
+            points,
+            generator_controls.join(),
+            supervisor.cancel_token(),
+            metrics,
+        );
+        Some(
+            generator
+                .with_stamps(args.latency)
+                .with_allocation(allocation)
+                .with_faults(faults),
+        )
+    };
+    let generators = (0..args.num_generators)
+        .filter_map(new_generator)
+        .collect::<Vec<_>>();
+
+    // Managers added during the run take their place in the reduction
+    // tree from a roster (so the tree stays open for them until the last
+    // manager is done), and their points receiver from a weak handle (so
+    // we don't keep the points channel open ourselves).
+    let managers = Arc::new(Roster::new(report_senders));
+    let points_receiver = Arc::downgrade(&managers_receiver);
+    let manager_controls = ControlGroup::new();
+    let new_manager = |i: usize| {
+        let point_receiver = points_receiver.upgrade()?;
+        let (member, mut report_senders) = managers.enlist()?;
+        let report_sender = report_senders.swap_remove(i % report_senders.len());
+        let metrics =
+            Arc::new(StageMetrics::new("manager", format!("manager {i}")).with_input("points"));
+        register(Arc::clone(&metrics));
+        let faults = FaultPlan::new(&metrics, &injections);
+        let manager = PointManager::new(
+            args.manager_cadence,
+            report_sender,
+            point_receiver,
+            manager_controls.join(),
+            supervisor.cancel_token(),
+            metrics,
+        );
+        Some(
+            manager
+                .with_strata(allocation.map(|a| a.strata))
+                .with_moments(args.variance_reduction)
+                .with_faults(faults)
+                .with_member(member),
+        )
+    };
+    let managers = (0..args.num_managers)
+        .filter_map(new_manager)
+        .collect::<Vec<_>>();
+
+    let (topology_sender, topology_receiver) = unbounded();
+    let reporter = reporter
+        .with_controls(generator_controls.clone(), manager_controls.clone())
+        .with_counted(counted)
+        .with_checkpoints(args.checkpoint.clone().map(|path| Checkpointer {
+            path,
+            ticker: Ticker::new(args.checkpoint_every),
+            requested_points: num_points,
+            first_point,
+            first_stream,
+            rng: point_rng,
+            dimensions: D,
+            seed,
+            source: Arc::clone(&source) as Arc<dyn Progress>,
+        }))
+        .with_commands(
+            if args.interactive {
+                read_commands()
+            } else {
+                never()
+            },
+            topology_sender,
+        );
+
+    aggregators
+        .iter()
+        .map(Aggregator::metrics)
+        .chain([reporter.metrics()])
+        .for_each(register);
+    let stages = registry.snapshot();
+    if let Some(injection) = args
+        .inject
+        .iter()
+        .find(|i| !stages.iter().any(|s| i.applies_to(s)))
+    {
+        return Err(PipelineError::ConfigInvalid(format!(
+            "there's no stage to inject {injection} into"
+        )));
+    }
+    let points_link = Link::new("points", points_receiver.clone());
+    let links = [points_link.clone()]
+        .into_iter()
+        .chain(
+            aggregators
+                .iter()
+                .map(|a| Link::new(a.metrics().name(), a.get_receiver())),
+        )
+        .chain([Link::new("reports", reporter.get_receiver())])
+        .collect::<Vec<_>>();
+    let (monitor, stop_monitor) = args
+        .monitor_ms
+        .map(|ms| Monitor::new(links.clone(), Duration::from_millis(ms)))
+        .unzip();
+    let (watchdog, stop_watchdog) = (args.watchdog_ms > 0)
+        .then(|| {
+            Watchdog::new(
+                registry.clone(),
+                links.clone(),
+                Duration::from_millis(args.watchdog_ms),
+            )
+        })
+        .unzip();
+    let dashboard = Dashboard::new(
+        args.display,
+        args.format,
+        num_points,
+        registry.clone(),
+        links,
+    )
+    .with_dimensions(D);
+
+    let autoscale_ticks = if args.autoscale {
+        tick(AUTOSCALE_INTERVAL)
+    } else {
+        never()
+    };
+    let mut autoscaler = Autoscaler::new(
+        args.channel_capacity
+            .map_or(AUTOSCALE_HIGH_WATER, |capacity| (capacity / 2).max(1)),
+        args.max_managers
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+    );
+
+    // It is crucial to drop this clone of the receiver used to
+    // communicate between the generators and the managers so that the
+    // channel can be properly closed when the generators are finished
+    // adding points to the channel. (The pool takes care of the sender.)
     drop(managers_receiver);
-    drop(generator_sender);
 
-    thread::scope(|s| {
-        for g in generators {
+    let mut failures = Failures::new();
+    let epoch = Instant::now();
+    let (num_sent, total, occupancies, latencies, strata, moments) = thread::scope(|s| {
+        // Every stage runs under the supervisor, so that if any of them
+        // fails the reporter finds out and the rest are cancelled.
+        let supervisor = &supervisor;
+        let spawn_generator = |g: MessageGenerator<D>| {
+            let name = g.metrics.name().to_string();
+            (
+                name.clone(),
+                s.spawn(move || supervisor.run(&name, || g.run())),
+            )
+        };
+        let mut generator_threads = generators
+            .into_iter()
+            .map(spawn_generator)
+            .collect::<Vec<_>>();
+        let spawn_manager = |mut m: PointManager<D>| {
+            let name = m.metrics.name().to_string();
+            (
+                name.clone(),
+                s.spawn(move || supervisor.run_restarting(&name, args.restart, || m.run())),
+            )
+        };
+        let mut manager_threads = managers.into_iter().map(spawn_manager).collect::<Vec<_>>();
+        let aggregator_threads = aggregators
+            .into_iter()
+            .map(|a| {
+                let name = a.metrics().name().to_string();
+                (
+                    name.clone(),
+                    s.spawn(move || supervisor.run(&name, || a.run())),
+                )
+            })
+            .collect::<Vec<_>>();
+        let reporter_thread =
+            s.spawn(move || supervisor.run("reporter", || reporter.run(dashboard)));
+        let monitor_thread = monitor.map(|m| s.spawn(move || m.run()));
+        // If the pipeline is stuck, none of the joins below will ever
+        // return, so there's no way out but to exit from right here.
+        let watchdog_thread = watchdog.map(|w| {
             s.spawn(move || {
-                g.send_messages();
-            });
+                if let Err(e) = w.run() {
+                    eprintln!("Error: {e}");
+                    process::exit(1);
+                }
+            })
+        });
+
+        // Carry out the commands that change which stages are running,
+        // from the operator, the autoscaler, or Ctrl-C, until the reporter
+        // is done and stops sending them.
+        let mut next_generator = args.num_generators;
+        let mut next_manager = args.num_managers;
+        loop {
+            let command = select! {
+                recv(topology_receiver) -> command => match command {
+                    Ok(command) => command,
+                    Err(_) => break,
+                },
+                recv(interrupts) -> _ => {
+                    eprintln!(
+                        "Interrupted, so finishing up with the points generated so far. \
+                         Interrupt again to quit right away."
+                    );
+                    Command::Stop
+                }
+                recv(autoscale_ticks) -> _ => {
+                    let queued = points_link.len();
+                    match autoscaler.sample(queued, registry.running("manager")) {
+                        Scaling::Add => Command::AddManager,
+                        Scaling::Remove => Command::RemoveManager,
+                        Scaling::Hold => continue,
+                    }
+                }
+            };
+            match command {
+                Command::AddGenerator => match new_generator(next_generator) {
+                    Some(g) => {
+                        eprintln!("Added {}.", g.metrics.name());
+                        generator_threads.push(spawn_generator(g));
+                        next_generator += 1;
+                    }
+                    None => eprintln!("There are no points left for a new generator."),
+                },
+                Command::RemoveGenerator => {
+                    if registry.running("generator") <= 1 {
+                        eprintln!("Can't remove the last generator.");
+                    } else if generator_controls.retire_one() {
+                        eprintln!("Removed a generator.");
+                    }
+                }
+                Command::AddManager => match new_manager(next_manager) {
+                    Some(m) => {
+                        eprintln!("Added {}.", m.metrics.name());
+                        manager_threads.push(spawn_manager(m));
+                        next_manager += 1;
+                    }
+                    None => eprintln!("The managers have already finished."),
+                },
+                Command::RemoveManager => {
+                    if registry.running("manager") <= 1 {
+                        eprintln!("Can't remove the last manager.");
+                    } else if manager_controls.retire_one() {
+                        eprintln!("Removed a manager.");
+                    }
+                }
+                // Paused stages are woken up by `Shutdown`, and everything
+                // that has already been generated still gets counted.
+                Command::Stop => {
+                    source.stop();
+                    generator_controls.send(Control::Shutdown);
+                    manager_controls.send(Control::Shutdown);
+                    eprintln!("Stopping early.");
+                }
+                // The reporter handles these itself.
+                Command::Status | Command::Pause | Command::Resume | Command::Flush => {}
+            }
+        }
+
+        // The reporter is the last stage to finish, so once it's done
+        // there's nothing left to monitor.
+        let total = failures.check(join_stage("reporter", reporter_thread));
+        drop(stop_monitor);
+        let occupancies = monitor_thread.and_then(|t| t.join().ok());
+
+        let mut num_sent = 0;
+        for (name, t) in generator_threads {
+            num_sent += failures.check(join_stage(&name, t)).unwrap_or(0);
         }
-        for m in managers {
-            s.spawn(move || m.receive_stuff());
+        let mut latencies = Histogram::new();
+        let mut strata = allocation.map(|a| StrataTally::new(a.strata));
+        let mut moments = args.variance_reduction.then(Moments::default);
+        for (name, t) in manager_threads {
+            if let Some(classified) = failures.check(join_stage(&name, t)) {
+                latencies.merge(&classified.latencies);
+                if let (Some(strata), Some(theirs)) = (&mut strata, &classified.strata) {
+                    strata.merge(theirs);
+                }
+                if let (Some(moments), Some(theirs)) = (&mut moments, classified.moments) {
+                    *moments = moments.merge(theirs);
+                }
+            }
         }
-        s.spawn(move || {
-            reporter.report_stuff();
-        });
+        for (name, t) in aggregator_threads {
+            failures.check(join_stage(&name, t));
+        }
+        drop(stop_watchdog);
+        if let Some(t) = watchdog_thread {
+            let _ = t.join();
+        }
+        (num_sent, total, occupancies, latencies, strata, moments)
     });
+    let elapsed = epoch.elapsed();
+
+    if let Some(total) = total
+        && failures.is_empty()
+        && total.total_num_points - counted.total_num_points != num_sent
+    {
+        failures.push(PipelineError::AccountingMismatch {
+            expected: num_sent,
+            counted: total.total_num_points - counted.total_num_points,
+        });
+    }
+
+    let stages = registry.snapshot();
+    if let Some(path) = &args.save
+        && let Some(total) = total
+        && failures.is_empty()
+    {
+        let count = |kind| stages.iter().filter(|s| s.kind() == kind).count();
+        let result = RunResult {
+            counted: total,
+            rng: point_rng,
+            dimensions: D,
+            seed,
+            points: first_point..source.position(),
+            streams: first_stream..source.next_stream(),
+            generators: count("generator"),
+            managers: count("manager"),
+            fan_in: args.fan_in,
+            elapsed,
+        };
+        match result.save(path) {
+            Ok(()) => eprintln!("Saved the results to {}", path.display()),
+            Err(e) => eprintln!("Failed to save the results to {}: {e}", path.display()),
+        }
+    }
+    if let Some(strata) = strata
+        && let Some(total) = total
+        && failures.is_empty()
+    {
+        match strata.estimate(D) {
+            Some(estimate) if args.format != OutputFormat::Text => {
+                let record =
+                    Record::from_estimate(RecordKind::Stratified, &total, estimate, elapsed);
+                write_record(args.format, &record);
+            }
+            Some((estimate, std_error)) => println!(
+                "Stratified estimate from {} strata is {estimate} ± {std_error}, with {:.1} times less variance than the plain estimate.",
+                allocation.map_or(0, |a| a.strata.len()),
+                (total.standard_error(D) / std_error).powi(2),
+            ),
+            None => eprintln!("Some strata have too few points for a stratified estimate."),
+        }
+    }
+    if let Some(moments) = moments
+        && let Some(total) = total
+        && failures.is_empty()
+    {
+        let estimates = [
+            ("Antithetic", RecordKind::Antithetic, moments.antithetic(D)),
+            (
+                "Control variate",
+                RecordKind::ControlVariate,
+                moments.control_variate(D),
+            ),
+        ];
+        for (name, kind, estimate) in estimates {
+            match estimate {
+                Some((estimate, std_error, _)) if args.format != OutputFormat::Text => {
+                    let record =
+                        Record::from_estimate(kind, &total, (estimate, std_error), elapsed);
+                    write_record(args.format, &record);
+                }
+                Some((estimate, std_error, Some(factor))) => println!(
+                    "{name} estimate is {estimate} ± {std_error}, with {factor:.1} times less variance than the plain estimate."
+                ),
+                Some((estimate, std_error, None)) => println!(
+                    "{name} estimate is {estimate} ± {std_error}, from points with no variance to reduce."
+                ),
+                None => eprintln!("Too few points for the {} estimate.", name.to_lowercase()),
+            }
+        }
+    }
+    if !args.no_metrics {
+        eprint!("{}", utilization_table(stages.iter().map(Arc::as_ref)));
+    }
+    if let Some(occupancies) = occupancies {
+        eprint!("{}", occupancy_table(&occupancies));
+    }
+    if args.latency {
+        eprintln!(
+            "Latency from generation to classification: {}",
+            latencies.summary()
+        );
+    }
+    if let Some(path) = &args.trace {
+        match write_chrome_trace(path, epoch, stages.iter().map(Arc::as_ref)) {
+            Ok(()) => eprintln!("Wrote trace to {}", path.display()),
+            Err(e) => eprintln!("Failed to write trace to {}: {e}", path.display()),
+        }
+    }
 
-    println!("All done!")
+    failures.into_result()?;
+    Ok(total.unwrap_or_default())
+}
+
+/// Run the pipeline `--replicates` times, and print the estimate the
+/// replicates give together. A replicate that's stopped early is left
+/// out, and no more are run after it.
+fn run_replicates(args: &Args, interrupts: &Receiver<()>) -> Result<(), PipelineError> {
+    if args.replicates == 0 {
+        return Err(PipelineError::ConfigInvalid(
+            "there must be at least one replicate".to_string(),
+        ));
+    }
+    let num_points = args.num_points.unwrap_or(NUM_POINTS);
+    let dimensions = args.dimensions.unwrap_or(2);
+    let mut replicates = Replicates::default();
+    let mut counted = Report::default();
+    let start = Instant::now();
+    for replicate in 0..args.replicates {
+        eprintln!("Running replicate {} of {}", replicate + 1, args.replicates);
+        let args = Args {
+            seed: args.seed.map(|seed| seed.wrapping_add(replicate as u64)),
+            ..args.clone()
+        };
+        let total = run(&args, interrupts)?;
+        if total.total_num_points < num_points {
+            eprintln!(
+                "Leaving out replicate {}, which was stopped early",
+                replicate + 1
+            );
+            break;
+        }
+        replicates.add(total, dimensions);
+        counted += total;
+    }
+
+    if replicates.len() < 2 {
+        eprintln!("It takes at least two replicates to estimate the error.");
+        return Ok(());
+    }
+    if args.format != OutputFormat::Text {
+        let record = Record::from_estimate(
+            RecordKind::Replicates,
+            &counted,
+            (replicates.estimate(), replicates.standard_error()),
+            start.elapsed(),
+        );
+        write_record(args.format, &record);
+        return Ok(());
+    }
+    let (low, high) = replicates.confidence_interval();
+    println!(
+        "Estimate from {} replicates of {num_points} points each is {} ± {} (95% CI [{low}, {high}]).",
+        replicates.len(),
+        replicates.estimate(),
+        replicates.standard_error(),
+    );
+    Ok(())
+}
+
+/// Combine the results saved by separate runs, and print the estimate
+/// they give together.
+fn merge_results(files: &[PathBuf], format: OutputFormat) -> Result<(), PipelineError> {
+    let results = files
+        .iter()
+        .map(|path| Ok((path.clone(), RunResult::load(path)?)))
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(PipelineError::ConfigInvalid)?;
+    let total = merge(&results).map_err(PipelineError::ConfigInvalid)?;
+    // `merge` makes sure the runs all had the same number of dimensions.
+    let dimensions = results[0].1.dimensions;
+    if format != OutputFormat::Text {
+        // The runs may well have been running at the same time, so their
+        // elapsed times add up to the computing time they took together.
+        let elapsed = results.iter().map(|(_, result)| result.elapsed).sum();
+        let record = Record::new(RecordKind::Merged, &total, dimensions, elapsed);
+        write_record(format, &record);
+        return Ok(());
+    }
+    let (low, high) = total.confidence_interval(dimensions);
+    println!(
+        "Combined estimate from {} runs after {} points is {} ± {} (95% CI [{low}, {high}]).",
+        results.len(),
+        total.total_num_points,
+        total.estimate(dimensions),
+        total.standard_error(dimensions),
+    );
+    Ok(())
 }
@@ -1,18 +1,45 @@
-use pi_estimation_channels::{NUM_POINTS, calculate_estimate, point::Point};
+use clap::Parser;
+use pi_estimation_channels::{
+    NUM_POINTS,
+    channels::{CountedReceiver, CountedSyncSender, Link, counted_sync_channel},
+    display::{Dashboard, DisplayMode},
+    error::{Failures, PipelineError, join_stage},
+    metrics::{StageMetrics, StageRegistry, utilization_table},
+    monitor::{Monitor, occupancy_table},
+    output::OutputFormat,
+    point::Point,
+    report::Report,
+};
 use rand::{
     Rng,
     distr::{Distribution, StandardUniform},
     rng,
 };
-use std::{
-    fmt::Debug,
-    sync::mpsc::{self, SyncSender, sync_channel},
-    thread,
-};
+use std::{fmt::Debug, process::ExitCode, sync::Arc, thread, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The format for progress updates and the final summary on stdout.
+    /// Diagnostic messages always go to stderr.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Don't measure how long each stage spends blocked sending and
+    /// receiving, or print the table of per-stage utilization at the end
+    /// of the run. The timing adds noticeable overhead to every message.
+    #[arg(long)]
+    no_metrics: bool,
+    /// Sample how many messages are waiting in each channel every this
+    /// many milliseconds (at least 1), and print the min/mean/max for each
+    /// channel at the end of the run.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    monitor_ms: Option<u64>,
+}
 
 struct MessageGenerator<T> {
     num_messages: usize,
-    send_channel: SyncSender<T>,
+    send_channel: CountedSyncSender<T>,
+    metrics: Arc<StageMetrics>,
 }
 
 impl<T> MessageGenerator<T>
@@ -20,69 +47,157 @@ where
     T: Default + Debug,
     StandardUniform: Distribution<T>,
 {
-    pub fn new(num_messages: usize, send_channel: SyncSender<T>) -> Self {
+    pub fn new(
+        num_messages: usize,
+        send_channel: CountedSyncSender<T>,
+        metrics: Arc<StageMetrics>,
+    ) -> Self {
         Self {
             num_messages,
             send_channel,
+            metrics,
         }
     }
 
-    pub fn send_messages(&self) {
+    /// Generate and send all our points, returning how many we sent.
+    pub fn run(&self) -> Result<usize, PipelineError> {
+        self.metrics.start();
         for _ in 0..self.num_messages {
             let value = rng().random();
-            self.send_channel.send(value).unwrap();
+            self.metrics
+                .time_send(|| self.send_channel.send(value))
+                .map_err(|_| PipelineError::disconnected("generator", "points"))?;
+            self.metrics.add_messages(1);
         }
+        self.metrics.finish();
+        Ok(self.num_messages)
     }
 }
 
 struct PointManager {
-    num_points: usize,
-    receive_channel: mpsc::Receiver<Point>,
+    receive_channel: CountedReceiver<Point>,
+    metrics: Arc<StageMetrics>,
 }
 
 impl PointManager {
-    pub fn new(num_points: usize, receive_channel: mpsc::Receiver<Point>) -> Self {
+    pub fn new(receive_channel: CountedReceiver<Point>, metrics: Arc<StageMetrics>) -> Self {
         Self {
-            num_points,
             receive_channel,
+            metrics,
         }
     }
 
-    pub fn receive_stuff(&self) {
+    /// Classify points until the generator is done, showing the estimate
+    /// on `dashboard` as we go, and return the count of all the points.
+    pub fn run(&self, dashboard: &mut Dashboard) -> Result<Report, PipelineError> {
         let mut num_inside = 0;
         let mut total_points = 0;
 
-        // TODO: Change to `while let`
-        for i in 0..self.num_points {
-            let point: Point = self.receive_channel.recv().unwrap();
-            if point.inside_unit_circle() {
+        self.metrics.start();
+        while let Ok(point) = self.metrics.time_recv(|| self.receive_channel.recv()) {
+            self.metrics.add_messages(1);
+            if point.inside_unit_ball() {
                 num_inside += 1;
             }
             total_points += 1;
 
-            if i % 10_000 == 0 {
-                let estimate = calculate_estimate(num_inside, total_points);
-                println!("Estimate after {i} points is {estimate}.");
+            if total_points % 10_000 == 0 {
+                dashboard.update(&Report::new(num_inside, total_points));
             }
         }
+        self.metrics.finish();
+
+        Ok(Report::new(num_inside, total_points))
     }
 }
 
-fn main() {
-    let (send_channel, receive_channel) = sync_channel::<Point>(1_000);
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => {
+            eprintln!("All done!");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    }
+}
 
-    let sender_thread = thread::spawn(move || {
-        let sender = MessageGenerator::new(NUM_POINTS, send_channel);
-        sender.send_messages();
-    });
+fn run(args: Args) -> Result<(), PipelineError> {
+    let registry = StageRegistry::new();
+    let stage = |kind, name: &str| {
+        let metrics = Arc::new(StageMetrics::new(kind, name));
+        metrics.set_timing(!args.no_metrics);
+        registry.register(Arc::clone(&metrics));
+        metrics
+    };
+    let generator_metrics = stage("generator", "generator");
+    let manager_metrics = stage("manager", "manager");
 
-    let receiver_thread = thread::spawn(move || {
-        let receiver = PointManager::new(NUM_POINTS, receive_channel);
-        receiver.receive_stuff();
-    });
+    let (send_channel, receive_channel) = counted_sync_channel::<Point>(1_000);
+    let (monitor, stop_monitor) = args
+        .monitor_ms
+        .map(|ms| {
+            let links = vec![Link::new("points", receive_channel.gauge())];
+            Monitor::new(links, Duration::from_millis(ms))
+        })
+        .unzip();
+    let mut dashboard = Dashboard::new(
+        DisplayMode::Plain,
+        args.format,
+        NUM_POINTS,
+        registry.clone(),
+        Vec::new(),
+    );
+
+    let mut failures = Failures::new();
+    let (total, occupancies) = thread::scope(|s| {
+        let sender_thread = s.spawn(move || {
+            let sender = MessageGenerator::new(NUM_POINTS, send_channel, generator_metrics);
+            sender.run()
+        });
+
+        let dashboard = &mut dashboard;
+        let receiver_thread = s.spawn(move || {
+            let receiver = PointManager::new(receive_channel, manager_metrics);
+            receiver.run(dashboard)
+        });
+        let monitor_thread = monitor.map(|m| s.spawn(move || m.run()));
 
-    sender_thread.join().unwrap();
-    receiver_thread.join().unwrap();
+        let num_sent = failures
+            .check(join_stage("generator", sender_thread))
+            .unwrap_or(0);
+        // The manager is the last stage to finish, so once it's done
+        // there's nothing left to monitor.
+        let total = failures.check(join_stage("manager", receiver_thread));
+        drop(stop_monitor);
+        let occupancies = monitor_thread.and_then(|t| t.join().ok());
+        if let Some(total) = total
+            && failures.is_empty()
+            && total.total_num_points != num_sent
+        {
+            failures.push(PipelineError::AccountingMismatch {
+                expected: num_sent,
+                counted: total.total_num_points,
+            });
+        }
+        (total, occupancies)
+    });
 
-    println!("All done!")
+    let result = failures.into_result();
+    if let Some(total) = total {
+        match &result {
+            Ok(()) => dashboard.finish(&total),
+            Err(error) => dashboard.abort(&total, error),
+        }
+    }
+    if !args.no_metrics {
+        let stages = registry.snapshot();
+        eprint!("{}", utilization_table(stages.iter().map(Arc::as_ref)));
+    }
+    if let Some(occupancies) = occupancies {
+        eprint!("{}", occupancy_table(&occupancies));
+    }
+    result
 }
@@ -1,19 +1,45 @@
-use pi_estimation_channels::{NUM_POINTS, calculate_estimate, point::Point};
+use clap::Parser;
+use pi_estimation_channels::{
+    NUM_POINTS,
+    channels::{CountedReceiver, CountedSyncSender, Link, OccupancyGauge, counted_sync_channel},
+    display::{Dashboard, DisplayMode},
+    error::{Failures, PipelineError, join_stage},
+    metrics::{StageMetrics, StageRegistry, utilization_table},
+    monitor::{Monitor, occupancy_table},
+    output::OutputFormat,
+    point::Point,
+    report::Report,
+};
 use rand::{
     Rng,
     distr::{Distribution, StandardUniform},
     rng,
 };
-use std::{
-    fmt::Debug,
-    iter::repeat_with,
-    sync::mpsc::{self, Receiver, SyncSender, sync_channel},
-    thread,
-};
+use std::{fmt::Debug, process::ExitCode, sync::Arc, thread, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// The format for progress updates and the final summary on stdout.
+    /// Diagnostic messages always go to stderr.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    /// Don't measure how long each stage spends blocked sending and
+    /// receiving, or print the table of per-stage utilization at the end
+    /// of the run. The timing adds noticeable overhead to every message.
+    #[arg(long)]
+    no_metrics: bool,
+    /// Sample how many messages are waiting in each channel every this
+    /// many milliseconds (at least 1), and print the min/mean/max for each
+    /// channel at the end of the run.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    monitor_ms: Option<u64>,
+}
 
 struct MessageGenerator<T> {
     num_messages: usize,
-    send_channels: Vec<SyncSender<T>>,
+    send_channels: Vec<CountedSyncSender<T>>,
+    metrics: Arc<StageMetrics>,
 }
 
 impl<T> MessageGenerator<T>
@@ -21,143 +47,254 @@ where
     T: Default + Debug,
     StandardUniform: Distribution<T>,
 {
-    pub fn new(num_messages: usize, send_channels: &[SyncSender<T>]) -> Self {
+    pub fn new(
+        num_messages: usize,
+        send_channels: &[CountedSyncSender<T>],
+        metrics: Arc<StageMetrics>,
+    ) -> Self {
         Self {
             num_messages,
             send_channels: send_channels.to_vec(),
+            metrics,
         }
     }
 
-    pub fn send_messages(self) {
+    /// Generate and send all our points, returning how many we sent.
+    pub fn run(self) -> Result<usize, PipelineError> {
+        self.metrics.start();
         let num_channels = self.send_channels.len();
         for i in 0..self.num_messages {
             let value = rng().random();
-            self.send_channels[i % num_channels].send(value).unwrap();
+            self.metrics
+                .time_send(|| self.send_channels[i % num_channels].send(value))
+                .map_err(|_| PipelineError::disconnected("generator", "points"))?;
+            self.metrics.add_messages(1);
         }
-        println!("Done sending messages");
+        self.metrics.finish();
+        eprintln!("Done sending messages");
+        Ok(self.num_messages)
     }
 }
 
 struct PointManager {
-    report_sender: SyncSender<Report>,
-    point_sender: SyncSender<Point>,
-    point_receiver: mpsc::Receiver<Point>,
+    report_sender: CountedSyncSender<Report>,
+    point_sender: CountedSyncSender<Point>,
+    point_receiver: CountedReceiver<Point>,
+    metrics: Arc<StageMetrics>,
 }
 
 impl PointManager {
-    pub fn new(report_sender: SyncSender<Report>) -> Self {
-        let (point_sender, point_receiver) = sync_channel(1_000);
+    pub fn new(report_sender: CountedSyncSender<Report>, metrics: Arc<StageMetrics>) -> Self {
+        let (point_sender, point_receiver) = counted_sync_channel(1_000);
         Self {
             report_sender,
             point_sender,
             point_receiver,
+            metrics,
         }
     }
 
-    pub fn get_sender(&self) -> SyncSender<Point> {
+    pub fn get_sender(&self) -> CountedSyncSender<Point> {
         self.point_sender.clone()
     }
 
-    pub fn receive_stuff(self) {
+    pub fn get_gauge(&self) -> OccupancyGauge {
+        self.point_receiver.gauge()
+    }
+
+    pub fn run(self) -> Result<(), PipelineError> {
         let mut num_inside = 0;
         let mut total_points = 0;
 
+        self.metrics.start();
         drop(self.point_sender);
-        while let Ok(point) = self.point_receiver.recv() {
-            if point.inside_unit_circle() {
+        while let Ok(point) = self.metrics.time_recv(|| self.point_receiver.recv()) {
+            self.metrics.add_messages(1);
+            if point.inside_unit_ball() {
                 num_inside += 1;
             }
             total_points += 1;
 
             if total_points % 10_000 == 0 {
-                self.report_sender
-                    .send(Report::new(num_inside, total_points))
-                    .unwrap();
+                self.metrics
+                    .time_send(|| {
+                        self.report_sender
+                            .send(Report::new(num_inside, total_points))
+                    })
+                    .map_err(|_| PipelineError::disconnected("manager", "reports"))?;
                 num_inside = 0;
                 total_points = 0;
             }
         }
 
-        println!("Done receiving messages & classifying points");
-    }
-}
-
-#[derive(Debug)]
-struct Report {
-    num_inside_points: usize,
-    total_num_points: usize,
-}
-
-impl Report {
-    pub fn new(num_inside_points: usize, total_num_points: usize) -> Self {
-        Report {
-            num_inside_points,
-            total_num_points,
+        if total_points > 0 {
+            self.metrics
+                .time_send(|| {
+                    self.report_sender
+                        .send(Report::new(num_inside, total_points))
+                })
+                .map_err(|_| PipelineError::disconnected("manager", "reports"))?;
         }
+        self.metrics.finish();
+
+        eprintln!("Done receiving messages & classifying points");
+        Ok(())
     }
 }
 
 struct Reporter {
-    sender: SyncSender<Report>,
-    receiver: Receiver<Report>,
+    sender: CountedSyncSender<Report>,
+    receiver: CountedReceiver<Report>,
+    metrics: Arc<StageMetrics>,
 }
 
 impl Reporter {
-    pub fn new() -> Self {
-        let (sender, receiver) = sync_channel(1_000);
-        Self { sender, receiver }
+    pub fn new(metrics: Arc<StageMetrics>) -> Self {
+        let (sender, receiver) = counted_sync_channel(1_000);
+        Self {
+            sender,
+            receiver,
+            metrics,
+        }
     }
 
-    pub fn get_sender(&self) -> SyncSender<Report> {
+    pub fn get_sender(&self) -> CountedSyncSender<Report> {
         self.sender.clone()
     }
 
-    pub fn report_stuff(self) {
-        let mut num_inside_points = 0;
-        let mut total_num_points = 0;
+    pub fn get_gauge(&self) -> OccupancyGauge {
+        self.receiver.gauge()
+    }
+
+    /// Show the running estimate on `dashboard` as the reports come in,
+    /// and return the combined report of all the points that were counted.
+    pub fn run(self, dashboard: &mut Dashboard) -> Result<Report, PipelineError> {
+        let mut total = Report::default();
 
+        self.metrics.start();
         drop(self.sender);
 
-        while let Ok(report) = self.receiver.recv() {
-            // println!("{report:?}");
-            num_inside_points += report.num_inside_points;
-            total_num_points += report.total_num_points;
-            let estimate = calculate_estimate(num_inside_points, total_num_points);
-            println!(
-                "After {} points the estimate is {estimate}.",
-                total_num_points,
-            );
+        while let Ok(report) = self.metrics.time_recv(|| self.receiver.recv()) {
+            self.metrics.add_messages(1);
+            total = total.merge(report);
+            dashboard.update(&total);
         }
+        self.metrics.finish();
 
-        println!("Done generating reports");
+        eprintln!("Done generating reports");
+        Ok(total)
     }
 }
 
 const NUM_MANAGERS: usize = 2;
 
-fn main() {
-    let reporter = Reporter::new();
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => {
+            eprintln!("All done!");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            e.exit_code()
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), PipelineError> {
+    let registry = StageRegistry::new();
+    let stage = |kind, name: String| {
+        let metrics = Arc::new(StageMetrics::new(kind, name));
+        metrics.set_timing(!args.no_metrics);
+        registry.register(Arc::clone(&metrics));
+        metrics
+    };
 
-    let managers = repeat_with(|| PointManager::new(reporter.get_sender()))
-        .take(NUM_MANAGERS)
+    let generator_metrics = stage("generator", "generator".to_string());
+    let manager_metrics = (0..NUM_MANAGERS)
+        .map(|i| stage("manager", format!("manager {i}")))
+        .collect::<Vec<_>>();
+    let reporter = Reporter::new(stage("reporter", "reporter".to_string()));
+
+    let managers = manager_metrics
+        .into_iter()
+        .map(|metrics| PointManager::new(reporter.get_sender(), metrics))
         .collect::<Vec<_>>();
 
     let generator = MessageGenerator::new(
         NUM_POINTS,
         &managers.iter().map(|m| m.get_sender()).collect::<Vec<_>>(),
+        generator_metrics,
+    );
+
+    let (monitor, stop_monitor) = args
+        .monitor_ms
+        .map(|ms| {
+            let links = managers
+                .iter()
+                .enumerate()
+                .map(|(i, m)| Link::new(format!("manager {i}"), m.get_gauge()))
+                .chain([Link::new("reports", reporter.get_gauge())])
+                .collect();
+            Monitor::new(links, Duration::from_millis(ms))
+        })
+        .unzip();
+
+    let mut dashboard = Dashboard::new(
+        DisplayMode::Plain,
+        args.format,
+        NUM_POINTS,
+        registry.clone(),
+        Vec::new(),
     );
 
-    thread::scope(|s| {
-        s.spawn(move || {
-            generator.send_messages();
-        });
-        for m in managers {
-            s.spawn(move || m.receive_stuff());
+    let mut failures = Failures::new();
+    let (total, occupancies) = thread::scope(|s| {
+        let generator_thread = s.spawn(move || generator.run());
+        let manager_threads = managers
+            .into_iter()
+            .map(|m| s.spawn(move || m.run()))
+            .collect::<Vec<_>>();
+        let dashboard = &mut dashboard;
+        let reporter_thread = s.spawn(move || reporter.run(dashboard));
+        let monitor_thread = monitor.map(|m| s.spawn(move || m.run()));
+
+        let num_sent = failures
+            .check(join_stage("generator", generator_thread))
+            .unwrap_or(0);
+        for t in manager_threads {
+            failures.check(join_stage("manager", t));
+        }
+        // The reporter is the last stage to finish, so once it's done
+        // there's nothing left to monitor.
+        let total = failures.check(join_stage("reporter", reporter_thread));
+        drop(stop_monitor);
+        let occupancies = monitor_thread.and_then(|t| t.join().ok());
+        if let Some(total) = total
+            && failures.is_empty()
+            && total.total_num_points != num_sent
+        {
+            failures.push(PipelineError::AccountingMismatch {
+                expected: num_sent,
+                counted: total.total_num_points,
+            });
         }
-        s.spawn(move || {
-            reporter.report_stuff();
-        });
+        (total, occupancies)
     });
 
-    println!("All done!")
+    let result = failures.into_result();
+    if let Some(total) = total {
+        match &result {
+            Ok(()) => dashboard.finish(&total),
+            Err(error) => dashboard.abort(&total, error),
+        }
+    }
+    if !args.no_metrics {
+        let stages = registry.snapshot();
+        eprint!("{}", utilization_table(stages.iter().map(Arc::as_ref)));
+    }
+    if let Some(occupancies) = occupancies {
+        eprint!("{}", occupancy_table(&occupancies));
+    }
+    result
 }
@@ -7,7 +7,7 @@ fn main() {
 
     for i in 0..NUM_POINTS {
         let point: Point = rng().random();
-        if point.inside_unit_circle() {
+        if point.inside_unit_ball() {
             num_inside += 1;
         } else {
             num_outside += 1;
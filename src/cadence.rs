@@ -0,0 +1,150 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// How often a stage should pass along what it has accumulated so far.
+///
+/// On the command line these are written as a bare number of points
+/// (`10000`), a number of milliseconds (`250ms`), or `adaptive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cadence {
+    /// Every time this many points have accumulated.
+    Points(usize),
+    /// Every time this much time has passed.
+    Interval(Duration),
+    /// Every time some number of points have accumulated, where that
+    /// number is adjusted on the fly so that updates come roughly every
+    /// [`ADAPTIVE_TARGET`].
+    Adaptive,
+}
+
+/// The interval that [`Cadence::Adaptive`] aims for.
+pub const ADAPTIVE_TARGET: Duration = Duration::from_millis(100);
+
+/// The number of points [`Cadence::Adaptive`] starts out waiting for
+/// before it has any timing information.
+const ADAPTIVE_INITIAL_POINTS: usize = 1_000;
+
+impl FromStr for Cadence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "adaptive" {
+            return Ok(Cadence::Adaptive);
+        }
+        if let Some(ms) = s.strip_suffix("ms") {
+            let ms = ms
+                .parse::<u64>()
+                .map_err(|e| format!("invalid number of milliseconds {ms:?}: {e}"))?;
+            if ms == 0 {
+                return Err("the interval must be at least 1ms".to_string());
+            }
+            return Ok(Cadence::Interval(Duration::from_millis(ms)));
+        }
+        match s.parse::<usize>() {
+            Ok(0) => Err("the number of points must be at least 1".to_string()),
+            Ok(n) => Ok(Cadence::Points(n)),
+            Err(_) => Err(format!(
+                "expected a number of points (e.g., 10000), a time (e.g., 250ms), or \"adaptive\", but got {s:?}"
+            )),
+        }
+    }
+}
+
+impl Display for Cadence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cadence::Points(n) => write!(f, "{n}"),
+            Cadence::Interval(d) => write!(f, "{}ms", d.as_millis()),
+            Cadence::Adaptive => write!(f, "adaptive"),
+        }
+    }
+}
+
+/// Keeps track of when the next update is due under a given [`Cadence`].
+///
+/// The owning stage calls [`Ticker::record`] as points come in, checks
+/// [`Ticker::is_due`], and calls [`Ticker::reset`] once it has actually
+/// sent or displayed an update.
+#[derive(Debug)]
+pub struct Ticker {
+    cadence: Cadence,
+    pending_points: usize,
+    since_clock_check: usize,
+    clock_stride: usize,
+    last_update: Instant,
+    adaptive_points: usize,
+}
+
+impl Ticker {
+    pub fn new(cadence: Cadence) -> Self {
+        Self {
+            cadence,
+            pending_points: 0,
+            since_clock_check: 0,
+            clock_stride: 1,
+            last_update: Instant::now(),
+            adaptive_points: ADAPTIVE_INITIAL_POINTS,
+        }
+    }
+
+    /// Only look at the clock once every `stride` points when using
+    /// [`Cadence::Interval`]. Reading the clock isn't free, and stages
+    /// that handle one point at a time don't need to do it for every
+    /// single point.
+    pub fn with_clock_stride(mut self, stride: usize) -> Self {
+        self.clock_stride = stride.max(1);
+        self
+    }
+
+    pub fn record(&mut self, num_points: usize) {
+        self.pending_points += num_points;
+        self.since_clock_check += num_points;
+    }
+
+    pub fn is_due(&mut self) -> bool {
+        match self.cadence {
+            Cadence::Points(n) => self.pending_points >= n,
+            Cadence::Adaptive => self.pending_points >= self.adaptive_points,
+            Cadence::Interval(interval) => {
+                // Without a stride we always check, even if nothing new
+                // has come in, so that a stage woken up by its deadline
+                // finds that it's due.
+                if self.clock_stride > 1 && self.since_clock_check < self.clock_stride {
+                    return false;
+                }
+                self.since_clock_check = 0;
+                self.last_update.elapsed() >= interval
+            }
+        }
+    }
+
+    /// When the next update is due, if that's determined by the clock.
+    /// Stages that block on a channel can use this as a deadline so that
+    /// they still update on time when nothing is arriving.
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.cadence {
+            Cadence::Interval(interval) => Some(self.last_update + interval),
+            Cadence::Points(_) | Cadence::Adaptive => None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        let now = Instant::now();
+        if self.cadence == Cadence::Adaptive {
+            // Double or halve the number of points we wait for depending
+            // on how far off the target the last update was.
+            let elapsed = now - self.last_update;
+            if elapsed < ADAPTIVE_TARGET / 2 {
+                self.adaptive_points = self.adaptive_points.saturating_mul(2);
+            } else if elapsed > ADAPTIVE_TARGET * 2 {
+                self.adaptive_points = (self.adaptive_points / 2).max(1);
+            }
+        }
+        self.pending_points = 0;
+        self.since_clock_check = 0;
+        self.last_update = now;
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::{
+    Arc, Weak,
+    atomic::{AtomicIsize, Ordering},
+    mpsc::{self, RecvError, SendError, SyncSender},
+};
+
+/// Anything we can ask "how many messages are waiting in you right
+/// now?". This is how the display (and anything else that wants to
+/// watch the pipeline) sees how full the channels are.
+pub trait QueueLen: Send + Sync {
+    fn len(&self) -> usize;
+
+    /// The bound on the channel, or `None` if it's unbounded.
+    fn capacity(&self) -> Option<usize>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Send> QueueLen for crossbeam_channel::Receiver<T> {
+    fn len(&self) -> usize {
+        crossbeam_channel::Receiver::len(self)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        crossbeam_channel::Receiver::capacity(self)
+    }
+}
+
+/// Watching a channel through a receiver keeps the channel open, so if the
+/// receiving stages all go away early the senders never find out, and
+/// block forever once it fills up. Sharing the receivers through an `Arc`
+/// and watching through a `Weak` avoids that. Once the receivers are gone
+/// the channel reads as empty.
+impl<T: Send> QueueLen for Weak<crossbeam_channel::Receiver<T>> {
+    fn len(&self) -> usize {
+        self.upgrade().map_or(0, |r| r.len())
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.upgrade().and_then(|r| r.capacity())
+    }
+}
+
+/// A named channel (a "link" between two layers of the pipeline) that
+/// we can check the occupancy of.
+#[derive(Clone)]
+pub struct Link {
+    name: String,
+    queue: Arc<dyn QueueLen>,
+}
+
+impl Link {
+    pub fn new(name: impl Into<String>, queue: impl QueueLen + 'static) -> Self {
+        Self {
+            name: name.into(),
+            queue: Arc::new(queue),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.queue.capacity()
+    }
+}
+
+/// Create a bounded standard library channel that keeps track of how
+/// many messages are in it.
+///
+/// Unlike crossbeam's channels, `std::sync::mpsc` has no way to ask how
+/// many messages are waiting, so this wraps both ends to count messages
+/// going in and coming out. Call [`CountedReceiver::gauge`] to get
+/// something that can be watched (e.g., wrapped in a [`Link`]).
+pub fn counted_sync_channel<T>(bound: usize) -> (CountedSyncSender<T>, CountedReceiver<T>) {
+    let (sender, receiver) = mpsc::sync_channel(bound);
+    let gauge = OccupancyGauge {
+        count: Arc::new(AtomicIsize::new(0)),
+        capacity: bound,
+    };
+    (
+        CountedSyncSender {
+            sender,
+            gauge: gauge.clone(),
+        },
+        CountedReceiver { receiver, gauge },
+    )
+}
+
+pub struct CountedSyncSender<T> {
+    sender: SyncSender<T>,
+    gauge: OccupancyGauge,
+}
+
+// Deriving `Clone` would require `T: Clone`, which we don't need.
+impl<T> Clone for CountedSyncSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            gauge: self.gauge.clone(),
+        }
+    }
+}
+
+impl<T> CountedSyncSender<T> {
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.sender.send(value)?;
+        self.gauge.count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+pub struct CountedReceiver<T> {
+    receiver: mpsc::Receiver<T>,
+    gauge: OccupancyGauge,
+}
+
+impl<T> CountedReceiver<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let value = self.receiver.recv()?;
+        self.gauge.count.fetch_sub(1, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    pub fn gauge(&self) -> OccupancyGauge {
+        self.gauge.clone()
+    }
+}
+
+/// A handle on the number of messages in a [`counted_sync_channel`].
+#[derive(Debug, Clone)]
+pub struct OccupancyGauge {
+    // The count is only updated after the fact on each end, so it can
+    // briefly be a little off in either direction (e.g., a receiver can
+    // take a message out and decrement before the sender that put it in
+    // gets around to incrementing). That's why this is signed, and why
+    // we clamp it to `0..=capacity` when reading it.
+    count: Arc<AtomicIsize>,
+    capacity: usize,
+}
+
+impl QueueLen for OccupancyGauge {
+    fn len(&self) -> usize {
+        (self.count.load(Ordering::Relaxed).max(0) as usize).min(self.capacity)
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        Some(self.capacity)
+    }
+}
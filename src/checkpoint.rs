@@ -0,0 +1,150 @@
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{report::Report, rng::PointRng};
+
+/// Enough about a run in progress to carry on with it later: what it
+/// has counted so far, and where it had got to in generating points.
+///
+/// `position` is the index of the next point that hadn't been handed
+/// out to a generator. Every point counted so far has a lower index,
+/// so a resumed run starts generating from there and never counts the
+/// same point twice. (Points that had been generated but not counted
+/// when the checkpoint was taken are skipped, which doesn't bias the
+/// estimate, since which points those were has nothing to do with where
+/// they landed.)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    /// The total number of points the run was asked for.
+    pub requested_points: usize,
+    pub counted: Report,
+    /// The index of the first point the run generated.
+    pub first_point: usize,
+    pub position: usize,
+    /// The index of the first generator stream the run used.
+    pub first_stream: usize,
+    /// The index of the next generator stream that hadn't been handed
+    /// out yet, which a resumed run starts from for the same reason it
+    /// starts from `position`.
+    pub next_stream: usize,
+    /// How the points are generated.
+    pub rng: PointRng,
+    /// How many dimensions the points have.
+    pub dimensions: usize,
+    /// The seed the points are generated from, if the run is seeded.
+    pub seed: Option<u64>,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to `path`. It's written to a temporary file
+    /// first and then moved into place, so a crash partway through
+    /// writing never leaves a truncated checkpoint behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, self.to_string())?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read checkpoint {}: {e}", path.display()))?
+            .parse()
+            .map_err(|e| format!("bad checkpoint {}: {e}", path.display()))
+    }
+}
+
+impl Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "requested_points={}", self.requested_points)?;
+        writeln!(f, "total_points={}", self.counted.total_num_points)?;
+        writeln!(f, "inside_points={}", self.counted.num_inside_points)?;
+        writeln!(f, "first_point={}", self.first_point)?;
+        writeln!(f, "position={}", self.position)?;
+        writeln!(f, "first_stream={}", self.first_stream)?;
+        writeln!(f, "next_stream={}", self.next_stream)?;
+        writeln!(f, "rng={}", self.rng)?;
+        writeln!(f, "dimensions={}", self.dimensions)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed={seed}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = String;
+
+    /// Parses the `key=value` lines written by `Display`, in any order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut requested_points = None;
+        let mut total_points = None;
+        let mut inside_points = None;
+        let mut first_point = None;
+        let mut position = None;
+        let mut first_stream = None;
+        let mut next_stream = None;
+        let mut rng = None;
+        let mut dimensions = None;
+        let mut seed = None;
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {line:?}"))?;
+            let field = match key {
+                "requested_points" => &mut requested_points,
+                "total_points" => &mut total_points,
+                "inside_points" => &mut inside_points,
+                "first_point" => &mut first_point,
+                "position" => &mut position,
+                "first_stream" => &mut first_stream,
+                "next_stream" => &mut next_stream,
+                "dimensions" => &mut dimensions,
+                "rng" => {
+                    rng = Some(value.parse()?);
+                    continue;
+                }
+                "seed" => {
+                    seed = Some(parse_number(key, value)?);
+                    continue;
+                }
+                _ => return Err(format!("unknown key {key:?}")),
+            };
+            *field = Some(parse_number(key, value)?);
+        }
+
+        let missing = |key| format!("missing {key}");
+        let counted = Report::new(
+            inside_points.ok_or_else(|| missing("inside_points"))?,
+            total_points.ok_or_else(|| missing("total_points"))?,
+        );
+        if counted.num_inside_points > counted.total_num_points {
+            return Err("more points inside the circle than in total".to_string());
+        }
+        Ok(Checkpoint {
+            requested_points: requested_points.ok_or_else(|| missing("requested_points"))?,
+            counted,
+            first_point: first_point.ok_or_else(|| missing("first_point"))?,
+            position: position.ok_or_else(|| missing("position"))?,
+            // Checkpoints from before there was a choice of rng don't say
+            // which they used, or anything about streams.
+            first_stream: first_stream.unwrap_or(0),
+            next_stream: next_stream.unwrap_or(0),
+            rng: rng.unwrap_or_default(),
+            // Nor do ones from before points could have more than two
+            // dimensions.
+            dimensions: dimensions.unwrap_or(2),
+            seed,
+        })
+    }
+}
+
+pub(crate) fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{key} should be a number, not {value:?}"))
+}
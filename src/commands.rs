@@ -0,0 +1,105 @@
+use std::{
+    fmt::{self, Display},
+    io::{self, BufRead},
+    str::FromStr,
+    thread,
+};
+
+use crossbeam_channel::{Receiver, unbounded};
+
+/// Commands an operator can type while a run is going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Show the current estimate and what each stage is doing.
+    Status,
+    /// Pause every generator and manager.
+    Pause,
+    Resume,
+    /// Have every manager send what it has counted so far.
+    Flush,
+    AddGenerator,
+    RemoveGenerator,
+    AddManager,
+    RemoveManager,
+    /// Stop generating points and finish up with the ones generated so
+    /// far.
+    Stop,
+}
+
+impl Command {
+    const ALL: [Command; 9] = [
+        Command::Status,
+        Command::Pause,
+        Command::Resume,
+        Command::Flush,
+        Command::AddGenerator,
+        Command::RemoveGenerator,
+        Command::AddManager,
+        Command::RemoveManager,
+        Command::Stop,
+    ];
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Command::ALL
+            .into_iter()
+            .find(|c| c.to_string() == s)
+            .ok_or_else(|| {
+                let names = Command::ALL.map(|c| c.to_string());
+                format!(
+                    "unknown command {s:?} (expected one of {})",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Status => write!(f, "status"),
+            Command::Pause => write!(f, "pause"),
+            Command::Resume => write!(f, "resume"),
+            Command::Flush => write!(f, "flush"),
+            Command::AddGenerator => write!(f, "add-generator"),
+            Command::RemoveGenerator => write!(f, "remove-generator"),
+            Command::AddManager => write!(f, "add-manager"),
+            Command::RemoveManager => write!(f, "remove-manager"),
+            Command::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+/// Start reading commands from stdin, one per line, returning a channel
+/// they'll be sent on. The channel disconnects when stdin is closed.
+///
+/// This runs on its own detached thread rather than as part of the
+/// pipeline, since there's no way to interrupt a blocking read from
+/// stdin, and we don't want the end of the run to wait on the operator
+/// hitting enter.
+pub fn read_commands() -> Receiver<Command> {
+    let (sender, receiver) = unbounded();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match line.parse() {
+                Ok(command) => {
+                    if sender.send(command).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    });
+    receiver
+}
@@ -0,0 +1,116 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crossbeam_channel::{Receiver, Sender, unbounded};
+
+use crate::sync::lock;
+
+/// Messages that tell a stage to change what it's doing, as opposed to
+/// the data it's there to work on. These go on a separate channel from
+/// the data, one per stage, so that they reach every stage rather than
+/// whichever one happens to pick them up first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Send whatever has been counted so far downstream right away,
+    /// rather than waiting until it's due.
+    Flush,
+    /// Stop working until told to `Resume`.
+    Pause,
+    Resume,
+    /// Finish early. Generators stop generating, and everyone else keeps
+    /// going until their input runs dry, so nothing that has already
+    /// been generated is lost.
+    Shutdown,
+    /// Leave the pipeline for good, passing on anything that's been
+    /// counted but not yet reported. This is for taking a stage out of a
+    /// run that otherwise carries on, so a manager leaves behind whatever
+    /// points are still queued for the others to pick up.
+    Retire,
+}
+
+/// The sending ends of the control channels for a group of stages (e.g.,
+/// all the managers), so a message can be sent to all of them at once.
+///
+/// Stages can join the group at any time, and clones of a group share
+/// the same set of stages. A stage that joins while the group is paused
+/// starts out paused too.
+#[derive(Debug, Clone, Default)]
+pub struct ControlGroup {
+    members: Arc<Mutex<Members>>,
+}
+
+#[derive(Debug, Default)]
+struct Members {
+    senders: Vec<Sender<Control>>,
+    /// Whether the last of `Pause` and `Resume` (or `Shutdown`, which
+    /// wakes paused stages too) sent to the group was `Pause`.
+    paused: bool,
+}
+
+impl ControlGroup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stage to the group, returning the receiver it should check
+    /// for control messages.
+    pub fn join(&self) -> Receiver<Control> {
+        let (sender, receiver) = unbounded();
+        let mut members = self.members();
+        if members.paused {
+            let _ = sender.send(Control::Pause);
+        }
+        members.senders.push(sender);
+        receiver
+    }
+
+    /// Send `control` to every stage in the group. Stages that have
+    /// already finished just don't get it.
+    pub fn send(&self, control: Control) {
+        let mut members = self.members();
+        match control {
+            Control::Pause => members.paused = true,
+            Control::Resume | Control::Shutdown => members.paused = false,
+            Control::Flush | Control::Retire => {}
+        }
+        for sender in &members.senders {
+            let _ = sender.send(control);
+        }
+    }
+
+    /// Tell the stage that joined most recently (and is still listening)
+    /// to `Retire`, and take it out of the group. Returns whether there
+    /// was any such stage.
+    pub fn retire_one(&self) -> bool {
+        let mut members = self.members();
+        while let Some(sender) = members.senders.pop() {
+            if sender.send(Control::Retire).is_ok() {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn members(&self) -> MutexGuard<'_, Members> {
+        lock(&self.members)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stages_that_join_while_paused_start_paused() {
+        let group = ControlGroup::new();
+        let early = group.join();
+        group.send(Control::Pause);
+        let late = group.join();
+        assert_eq!(early.try_recv(), Ok(Control::Pause));
+        assert_eq!(late.try_recv(), Ok(Control::Pause));
+
+        group.send(Control::Resume);
+        let after = group.join();
+        assert_eq!(late.try_recv(), Ok(Control::Resume));
+        assert!(after.try_recv().is_err(), "nothing to resume from");
+    }
+}
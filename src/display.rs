@@ -0,0 +1,279 @@
+use std::{
+    f64::consts::PI,
+    io::{self, IsTerminal, Write},
+    time::Instant,
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    channels::Link,
+    error::PipelineError,
+    metrics::{StageRegistry, StageState},
+    output::{OutputFormat, Record, RecordKind, write_record},
+    report::Report,
+    text::write_line,
+    unit_ball_volume,
+    watchdog::state_report,
+};
+
+/// How the reporter should show its progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DisplayMode {
+    /// Use the dashboard if stdout is a terminal, and plain text otherwise.
+    Auto,
+    /// A dashboard that's redrawn in place on every update.
+    Tui,
+    /// One line of text per update.
+    Plain,
+}
+
+impl DisplayMode {
+    /// Turn `Auto` into one of the other two based on where stdout goes.
+    pub fn resolve(self) -> Self {
+        match self {
+            DisplayMode::Auto if io::stdout().is_terminal() => DisplayMode::Tui,
+            DisplayMode::Auto => DisplayMode::Plain,
+            mode => mode,
+        }
+    }
+}
+
+// ANSI escape sequences used to redraw the dashboard.
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CURSOR_HOME: &str = "\x1b[H";
+const CLEAR_TO_END_OF_LINE: &str = "\x1b[K";
+const CLEAR_TO_END_OF_SCREEN: &str = "\x1b[J";
+
+/// The reporter's view of the pipeline: the running estimate along with
+/// how fast each kind of stage is going and how full each channel is.
+pub struct Dashboard {
+    mode: DisplayMode,
+    format: OutputFormat,
+    expected_points: usize,
+    /// How many dimensions the points are in.
+    dimensions: usize,
+    stages: StageRegistry,
+    links: Vec<Link>,
+    start: Instant,
+    /// When we last drew, and how many messages each stage had handled
+    /// at that point, so we can compute throughput between updates.
+    last_draw: Instant,
+    last_messages: Vec<usize>,
+    has_drawn: bool,
+}
+
+impl Dashboard {
+    /// Structured output formats are never drawn as a dashboard, since
+    /// they're meant to be read by other programs.
+    pub fn new(
+        mode: DisplayMode,
+        format: OutputFormat,
+        expected_points: usize,
+        stages: StageRegistry,
+        links: Vec<Link>,
+    ) -> Self {
+        let now = Instant::now();
+        Self {
+            mode: match format {
+                OutputFormat::Text => mode.resolve(),
+                OutputFormat::Json | OutputFormat::Csv => DisplayMode::Plain,
+            },
+            format,
+            expected_points,
+            dimensions: 2,
+            stages,
+            links,
+            start: now,
+            last_draw: now,
+            last_messages: Vec::new(),
+            has_drawn: false,
+        }
+    }
+
+    /// Estimate π from points in `dimensions` dimensions rather than two,
+    /// and show the estimate of the unit ball's volume too.
+    pub fn with_dimensions(mut self, dimensions: usize) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+
+    pub fn update(&mut self, total: &Report) {
+        match (self.format, self.mode) {
+            (OutputFormat::Text, DisplayMode::Tui) => self.draw(total),
+            (OutputFormat::Text, _) => println!(
+                "After {} points the estimate is {}.",
+                total.total_num_points,
+                total.estimate(self.dimensions),
+            ),
+            _ => self.write_record(RecordKind::Update, total),
+        }
+    }
+
+    /// Show the final estimate once all the reports are in.
+    pub fn finish(&mut self, total: &Report) {
+        let summary = Record::summary(
+            total,
+            self.dimensions,
+            self.expected_points,
+            self.start.elapsed(),
+        );
+        match (self.format, summary.kind) {
+            (OutputFormat::Text, RecordKind::Partial) => println!(
+                "Partial estimate after {} of {} points is {} ± {} ({:.2?}).",
+                total.total_num_points,
+                self.expected_points,
+                total.estimate(self.dimensions),
+                total.standard_error(self.dimensions),
+                summary.elapsed,
+            ),
+            (OutputFormat::Text, _) => println!(
+                "Final estimate after {} points is {} ± {} ({:.2?}).",
+                total.total_num_points,
+                total.estimate(self.dimensions),
+                total.standard_error(self.dimensions),
+                summary.elapsed,
+            ),
+            (OutputFormat::Json | OutputFormat::Csv, _) => write_record(self.format, &summary),
+        }
+        if self.format == OutputFormat::Text && self.dimensions != 2 {
+            println!(
+                "That's from an estimate of {} ± {} for the volume of the unit {}-ball, which is really {}.",
+                total.volume(self.dimensions),
+                total.volume_standard_error(self.dimensions),
+                self.dimensions,
+                unit_ball_volume(self.dimensions),
+            );
+        }
+    }
+
+    /// Show the current estimate and what every stage is doing on stderr,
+    /// whatever the display mode, so it isn't drawn over or mixed in with
+    /// structured output.
+    pub fn status(&self, total: &Report) {
+        eprintln!(
+            "After {} of {} points the estimate is {} ± {} ({:.2?}).",
+            total.total_num_points,
+            self.expected_points,
+            total.estimate(self.dimensions),
+            total.standard_error(self.dimensions),
+            self.start.elapsed(),
+        );
+        eprint!("{}", state_report(&self.stages.snapshot(), &self.links));
+    }
+
+    /// Report that the run failed partway through, so whatever estimate we
+    /// have is based on partial data and shouldn't be used.
+    pub fn abort(&mut self, total: &Report, error: &PipelineError) {
+        match self.format {
+            OutputFormat::Text => println!(
+                "Run invalid: {error}. Stopped after counting {} points.",
+                total.total_num_points,
+            ),
+            OutputFormat::Json | OutputFormat::Csv => {
+                self.write_record(RecordKind::Invalid, total);
+            }
+        }
+    }
+
+    fn write_record(&mut self, kind: RecordKind, total: &Report) {
+        let record = Record::new(kind, total, self.dimensions, self.start.elapsed());
+        write_record(self.format, &record);
+    }
+
+    fn draw(&mut self, total: &Report) {
+        let now = Instant::now();
+        let seconds = (now - self.last_draw).as_secs_f64();
+
+        let mut screen = String::new();
+        if !self.has_drawn {
+            screen.push_str(CLEAR_SCREEN);
+            self.has_drawn = true;
+        }
+        screen.push_str(CURSOR_HOME);
+
+        let mut lines = Vec::new();
+        let estimate = total.estimate(self.dimensions);
+        let (low, high) = total.confidence_interval(self.dimensions);
+        lines.push(format!("π estimate   {estimate:.8}"));
+        lines.push(format!(
+            "error vs π   {:+.8} ({:.5}%)",
+            estimate - PI,
+            100.0 * (estimate - PI).abs() / PI
+        ));
+        lines.push(format!("95% CI       [{low:.8}, {high:.8}]"));
+        if self.dimensions != 2 {
+            lines.push(format!(
+                "{:<13}{:.8} (really {:.8})",
+                format!("{}-ball", self.dimensions),
+                total.volume(self.dimensions),
+                unit_ball_volume(self.dimensions),
+            ));
+        }
+        lines.push(format!(
+            "points       {} / {} ({:.1}%)",
+            total.total_num_points,
+            self.expected_points,
+            100.0 * total.total_num_points as f64 / self.expected_points as f64
+        ));
+        lines.push(format!(
+            "elapsed      {:.1}s",
+            (now - self.start).as_secs_f64()
+        ));
+        lines.push(String::new());
+
+        // Throughput, summed over all the stages of each kind. Stages can
+        // be added during the run, and those that have finished no longer
+        // count as threads.
+        lines.push("stage          threads      messages/s".to_string());
+        let stages = self.stages.snapshot();
+        self.last_messages.resize(stages.len(), 0);
+        let mut kinds: Vec<(&str, usize, f64)> = Vec::new();
+        for (stage, last) in stages.iter().zip(self.last_messages.iter_mut()) {
+            let messages = stage.messages();
+            let rate = if seconds > 0.0 {
+                (messages - *last) as f64 / seconds
+            } else {
+                0.0
+            };
+            *last = messages;
+            let running = usize::from(stage.state() != StageState::Finished);
+            match kinds.iter_mut().find(|(kind, _, _)| *kind == stage.kind()) {
+                Some((_, threads, total_rate)) => {
+                    *threads += running;
+                    *total_rate += rate;
+                }
+                None => kinds.push((stage.kind(), running, rate)),
+            }
+        }
+        for (kind, threads, rate) in kinds {
+            lines.push(format!("  {kind:<12} {threads:>7} {rate:>15.0}"));
+        }
+        lines.push(String::new());
+
+        lines.push("channel        queued     capacity".to_string());
+        for link in &self.links {
+            let capacity = link
+                .capacity()
+                .map_or_else(|| "unbounded".to_string(), |c| c.to_string());
+            lines.push(format!(
+                "  {:<12} {:>7} {capacity:>12}",
+                link.name(),
+                link.len()
+            ));
+        }
+
+        for line in lines {
+            write_line(&mut screen, format_args!("{line}{CLEAR_TO_END_OF_LINE}"));
+        }
+        screen.push_str(CLEAR_TO_END_OF_SCREEN);
+
+        let mut stdout = io::stdout().lock();
+        // If stdout has gone away there's nothing useful we can do about
+        // it from in here, so we just skip this update.
+        let _ = stdout.write_all(screen.as_bytes());
+        let _ = stdout.flush();
+
+        self.last_draw = now;
+    }
+}
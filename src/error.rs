@@ -0,0 +1,163 @@
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{self, Display},
+    process::ExitCode,
+    thread::ScopedJoinHandle,
+    time::Duration,
+};
+
+/// The ways a run of one of the pipelines can go wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PipelineError {
+    /// A stage tried to send on a channel but everyone on the receiving
+    /// end has gone away, which usually means a downstream stage died.
+    Disconnected {
+        stage: String,
+        channel: &'static str,
+    },
+    /// A stage's thread panicked.
+    WorkerPanicked { stage: String, message: String },
+    /// The number of points the reporter counted doesn't match the
+    /// number the generators sent.
+    AccountingMismatch { expected: usize, counted: usize },
+    /// The pipeline was configured in a way that can't work.
+    ConfigInvalid(String),
+    /// A stage stopped early because the run was cancelled (usually
+    /// because some other stage failed).
+    Cancelled { stage: String },
+    /// No stage made any progress for this long, so the pipeline is
+    /// probably stuck.
+    Stalled { idle: Duration },
+}
+
+impl PipelineError {
+    pub fn disconnected(stage: impl Into<String>, channel: &'static str) -> Self {
+        PipelineError::Disconnected {
+            stage: stage.into(),
+            channel,
+        }
+    }
+
+    /// Whether this error is likely just a side effect of some other
+    /// failure (e.g., a generator finding that the managers are gone
+    /// because one of them panicked).
+    pub fn is_knock_on(&self) -> bool {
+        matches!(
+            self,
+            PipelineError::Disconnected { .. } | PipelineError::Cancelled { .. }
+        )
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            // This matches what `clap` uses for bad command line arguments.
+            PipelineError::ConfigInvalid(_) => ExitCode::from(2),
+            _ => ExitCode::FAILURE,
+        }
+    }
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Disconnected { stage, channel } => write!(
+                f,
+                "{stage} couldn't send on the {channel} channel because its receivers are gone"
+            ),
+            PipelineError::WorkerPanicked { stage, message } => {
+                write!(f, "{stage} panicked: {message}")
+            }
+            PipelineError::AccountingMismatch { expected, counted } => write!(
+                f,
+                "the generators sent {expected} points but the reporter counted {counted}"
+            ),
+            PipelineError::ConfigInvalid(reason) => write!(f, "invalid configuration: {reason}"),
+            PipelineError::Cancelled { stage } => write!(f, "{stage} was cancelled"),
+            PipelineError::Stalled { idle } => {
+                write!(f, "no stage has made any progress for {idle:.1?}")
+            }
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+/// Get the message out of a panic payload, which is almost always either
+/// a `&str` or a `String`.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "(no message)".to_string()
+    }
+}
+
+/// Wait for the thread running `stage` to finish, turning a panic into a
+/// [`PipelineError::WorkerPanicked`].
+pub fn join_stage<T>(
+    stage: &str,
+    handle: ScopedJoinHandle<'_, Result<T, PipelineError>>,
+) -> Result<T, PipelineError> {
+    handle.join().unwrap_or_else(|payload| {
+        Err(PipelineError::WorkerPanicked {
+            stage: stage.to_string(),
+            message: panic_message(&*payload),
+        })
+    })
+}
+
+/// Collects the errors from all the stages of a run so we can report
+/// the one that most likely caused the others.
+#[derive(Debug, Default)]
+pub struct Failures {
+    errors: Vec<PipelineError>,
+}
+
+impl Failures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hang on to the error (if any) from `result`, returning the value
+    /// if there was one.
+    pub fn check<T>(&mut self, result: Result<T, PipelineError>) -> Option<T> {
+        result.map_err(|e| self.push(e)).ok()
+    }
+
+    /// Add `error` unless we already have it (e.g., a panic that's both
+    /// returned from a stage and reported by the reporter).
+    pub fn push(&mut self, error: PipelineError) {
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Ok` if nothing went wrong, and otherwise the first error that
+    /// isn't just a knock-on effect of another one. Any other errors
+    /// (other than stages being cancelled, which is expected once
+    /// something has failed) are written to stderr so they aren't lost.
+    pub fn into_result(mut self) -> Result<(), PipelineError> {
+        let Some(primary) = self
+            .errors
+            .iter()
+            .position(|e| !e.is_knock_on())
+            .or((!self.errors.is_empty()).then_some(0))
+        else {
+            return Ok(());
+        };
+        let primary = self.errors.remove(primary);
+        for other in &self.errors {
+            if !matches!(other, PipelineError::Cancelled { .. }) {
+                eprintln!("Also: {other}");
+            }
+        }
+        Err(primary)
+    }
+}
@@ -0,0 +1,255 @@
+use crate::{estimate_pi, point::Point};
+
+/// The mean of the square of each coordinate over [-1, 1].
+const CONTROL_MEAN_PER_DIMENSION: f64 = 1.0 / 3.0;
+
+/// Running sums over classified points for two estimators that have
+/// less variance than just counting the points inside the circle (or
+/// ball, in more dimensions).
+///
+/// - The antithetic estimator classifies each point's partner as well,
+///   reflected within its quadrant (x becomes ±(1 - |x|), keeping its
+///   sign, and the same for y and any other coordinates). A point near
+///   the middle of the circle has a partner near a corner of the square,
+///   so partners tend to land on opposite sides of the circle's edge, and
+///   the average of the two varies less than two independent points
+///   would. (Reflecting through
+///   the origin or the axes would do nothing at all: the circle is
+///   symmetric, so the partner would always land on the same side.)
+/// - The control variate estimator uses x² + y² (plus the squares of
+///   any other coordinates), whose mean over the square we know exactly,
+///   to correct for how far off the points' distribution happened to
+///   be. Points with x² + y² ≤ 1 are the ones inside the circle, so the
+///   two are strongly correlated.
+///
+/// Like [`crate::report::Report`], these can be merged in any order.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Moments {
+    points: usize,
+    inside: usize,
+    /// How many points had both themselves and their partner inside,
+    /// and how many had just one of the two.
+    both_inside: usize,
+    one_inside: usize,
+    /// Sums of the control (the sum of the squares of the coordinates),
+    /// its square, and its product with whether the point was inside.
+    control: f64,
+    control_squared: f64,
+    control_inside: f64,
+}
+
+impl Moments {
+    pub fn record<const D: usize>(&mut self, point: &Point<D>, inside: bool) {
+        let partner = Point {
+            coordinates: point
+                .coordinates
+                .map(|coordinate| coordinate.signum() * (1.0 - coordinate.abs())),
+        };
+        match (inside, partner.inside_unit_ball()) {
+            (true, true) => self.both_inside += 1,
+            (true, false) | (false, true) => self.one_inside += 1,
+            (false, false) => {}
+        }
+
+        let control = point.coordinates.iter().map(|c| c * c).sum::<f64>();
+        self.points += 1;
+        self.inside += usize::from(inside);
+        self.control += control;
+        self.control_squared += control * control;
+        if inside {
+            self.control_inside += control;
+        }
+    }
+
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            points: self.points + other.points,
+            inside: self.inside + other.inside,
+            both_inside: self.both_inside + other.both_inside,
+            one_inside: self.one_inside + other.one_inside,
+            control: self.control + other.control,
+            control_squared: self.control_squared + other.control_squared,
+            control_inside: self.control_inside + other.control_inside,
+        }
+    }
+
+    /// The antithetic estimate of π from points in `dimensions`
+    /// dimensions and its standard error, along with how many times less
+    /// variance it has than the plain estimate from the same number of
+    /// classifications (twice the number of points), if that's defined.
+    /// `None` without at least two points.
+    pub fn antithetic(&self, dimensions: usize) -> Option<(f64, f64, Option<f64>)> {
+        if self.points < 2 {
+            return None;
+        }
+        let n = self.points as f64;
+        // Each point gives the average of it and its partner: 1, 1/2, or 0.
+        let sum = self.both_inside as f64 + self.one_inside as f64 / 2.0;
+        let sum_squared = self.both_inside as f64 + self.one_inside as f64 / 4.0;
+        let mean = sum / n;
+        let variance = ((sum_squared - n * mean * mean) / (n - 1.0)).max(0.0);
+        let plain_variance = mean * (1.0 - mean) / 2.0;
+        let (estimate, std_error) = estimate_pi(mean, (variance / n).sqrt(), dimensions);
+        Some((
+            estimate,
+            std_error,
+            variance_reduction(plain_variance, variance),
+        ))
+    }
+
+    /// The control variate estimate of π from points in `dimensions`
+    /// dimensions and its standard error, along with how many times less
+    /// variance it has than the plain estimate from the same points, if
+    /// that's defined. `None` without at least three points, or if the
+    /// control is the same for every point (so there's nothing to
+    /// regress on).
+    pub fn control_variate(&self, dimensions: usize) -> Option<(f64, f64, Option<f64>)> {
+        if self.points < 3 {
+            return None;
+        }
+        let n = self.points as f64;
+        let inside_mean = self.inside as f64 / n;
+        let control_mean = self.control / n;
+        // Sums of squares and products about the means.
+        let inside_inside = self.inside as f64 * (1.0 - inside_mean);
+        let control_control = self.control_squared - n * control_mean * control_mean;
+        let control_inside = self.control_inside - n * control_mean * inside_mean;
+        if control_control <= 0.0 {
+            return None;
+        }
+
+        // The best multiple of the control to subtract is the slope of
+        // the regression of inside on the control, which leaves the
+        // residual variance.
+        let slope = control_inside / control_control;
+        let expected = dimensions as f64 * CONTROL_MEAN_PER_DIMENSION;
+        let fraction = inside_mean - slope * (control_mean - expected);
+        let residual = ((inside_inside - slope * control_inside) / (n - 2.0)).max(0.0);
+        let plain_variance = inside_inside / (n - 1.0);
+        let (estimate, std_error) = estimate_pi(fraction, (residual / n).sqrt(), dimensions);
+        Some((
+            estimate,
+            std_error,
+            variance_reduction(plain_variance, residual),
+        ))
+    }
+}
+
+/// How many times less variance `reduced` is than `plain`. That's
+/// undefined when there's no variance to reduce, as when every point (or
+/// pair) is inside.
+fn variance_reduction(plain: f64, reduced: f64) -> Option<f64> {
+    let factor = plain / reduced;
+    (plain > 0.0 && reduced > 0.0 && factor.is_finite()).then_some(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moments(points: &[[f64; 2]]) -> Moments {
+        let mut moments = Moments::default();
+        for &[x, y] in points {
+            let point = Point::new(x, y);
+            moments.record(&point, point.inside_unit_ball());
+        }
+        moments
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn antithetic_pairs_average_each_point_with_its_partner() {
+        // Partners: (0.9, 0.9) is outside, (0.5, 0.5) is inside,
+        // (-0.1, 0.1) is inside, and (0.05, -0.05) is inside. So the pairs
+        // average 1/2, 1, 1/2, and 1/2, with mean 5/8 and sample variance
+        // 1/16.
+        let (estimate, std_error, factor) =
+            moments(&[[0.1, 0.1], [0.5, 0.5], [-0.9, 0.9], [0.95, -0.95]])
+                .antithetic(2)
+                .unwrap();
+        assert_close(estimate, 4.0 * 5.0 / 8.0);
+        assert_close(std_error, 4.0 * (1.0f64 / 16.0 / 4.0).sqrt());
+        // Against the plain estimate's (5/8)(3/8)/2 from twice the points.
+        assert_close(factor.unwrap(), (5.0 / 8.0) * (3.0 / 8.0) / 2.0 * 16.0);
+    }
+
+    #[test]
+    fn the_control_variate_corrects_by_the_regression_on_x2_plus_y2() {
+        // x² + y² is 1/4, 1/2, 2/3, and 5/4, which averages exactly 2/3,
+        // so there's nothing to correct and the estimate is the plain
+        // count's. Regressing inside on x² + y² has slope -14/13 and
+        // leaves a residual variance of 19/312, against the plain 1/4.
+        let (estimate, std_error, factor) = moments(&[
+            [0.5, 0.0],
+            [0.5, 0.5],
+            [(2.0f64 / 3.0).sqrt(), 0.0],
+            [1.0, 0.5],
+        ])
+        .control_variate(2)
+        .unwrap();
+        assert_close(estimate, 3.0);
+        assert_close(std_error, 4.0 * (19.0f64 / 312.0 / 4.0).sqrt());
+        assert_close(factor.unwrap(), 78.0 / 19.0);
+    }
+
+    #[test]
+    fn merging_is_the_same_as_recording_everything_together() {
+        let points = [[0.1, 0.1], [0.5, 0.5], [-0.9, 0.9], [0.95, -0.95]];
+        let merged = moments(&points[..1]).merge(moments(&points[1..]));
+        let together = moments(&points);
+        let (merged, together) = (
+            [merged.antithetic(2), merged.control_variate(2)],
+            [together.antithetic(2), together.control_variate(2)],
+        );
+        for (merged, together) in merged.into_iter().zip(together) {
+            let (merged, together) = (merged.unwrap(), together.unwrap());
+            assert_close(merged.0, together.0);
+            assert_close(merged.1, together.1);
+        }
+    }
+
+    #[test]
+    fn every_coordinate_counts_in_more_dimensions() {
+        let mut moments = Moments::default();
+        // Inside in two dimensions, but not with the third coordinate.
+        let point = Point {
+            coordinates: [0.5, 0.5, 0.9],
+        };
+        moments.record(&point, point.inside_unit_ball());
+        // Its partner, (0.5, 0.5, 0.1), is inside.
+        assert_eq!((moments.inside, moments.one_inside), (0, 1));
+        assert_close(moments.control, 0.25 + 0.25 + 0.81);
+    }
+
+    #[test]
+    fn no_variance_gives_no_reduction_factor() {
+        // Every point and its partner is inside, and the control varies.
+        let (estimate, std_error, factor) = moments(&[[0.5, 0.5], [0.4, 0.6], [0.6, 0.4]])
+            .antithetic(2)
+            .unwrap();
+        assert_eq!((estimate, std_error, factor), (4.0, 0.0, None));
+        let (estimate, std_error, factor) = moments(&[[0.1, 0.1], [0.5, 0.5], [0.2, 0.6]])
+            .control_variate(2)
+            .unwrap();
+        assert_close(estimate, 4.0);
+        assert_close(std_error, 0.0);
+        assert_eq!(factor, None);
+    }
+
+    #[test]
+    fn a_control_that_never_varies_gives_no_control_variate_estimate() {
+        let moments = moments(&[[0.5, 0.5], [-0.5, 0.5], [0.5, -0.5]]);
+        assert_eq!(moments.control_variate(2), None);
+    }
+
+    #[test]
+    fn too_few_points_give_no_estimates() {
+        let moments = moments(&[[0.1, 0.1]]);
+        assert_eq!(moments.antithetic(2), None);
+        assert_eq!(moments.control_variate(2), None);
+    }
+}
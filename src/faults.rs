@@ -0,0 +1,226 @@
+use std::{
+    cmp::Reverse,
+    fmt::{self, Display},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use crate::metrics::StageMetrics;
+
+/// Something going wrong in a stage on purpose, to check that the rest
+/// of the pipeline copes with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Panic.
+    Panic,
+    /// Stop doing anything for a while.
+    Stall(Duration),
+    /// Throw away this many messages instead of handling them.
+    Drop(usize),
+    /// Stop early, closing the stage's channels, but otherwise act as if
+    /// everything went fine.
+    Close,
+}
+
+/// The kinds of stage that faults can be injected into.
+const STAGE_KINDS: [&str; 4] = ["generator", "manager", "aggregator", "reporter"];
+
+/// A fault to inject into one or more stages.
+///
+/// On the command line these are written as `STAGE:FAULT[@N]`, where
+/// `STAGE` is a kind of stage (e.g., `manager`, meaning all of them) or a
+/// single stage (e.g., `manager1`), `FAULT` is one of `panic`,
+/// `stall=500ms`, `drop` (or `drop=10` for more than one message), or
+/// `close`, and `N` is the number of messages the stage handles before
+/// the fault happens (0 if it's left out).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Injection {
+    kind: String,
+    index: Option<usize>,
+    fault: Fault,
+    after: usize,
+}
+
+impl Injection {
+    /// Inject `fault` after `after` messages into the stage of the given
+    /// `kind` with the given `index`, or every stage of that kind if
+    /// `index` is `None`.
+    pub fn new(kind: impl Into<String>, index: Option<usize>, fault: Fault, after: usize) -> Self {
+        Self {
+            kind: kind.into(),
+            index,
+            fault,
+            after,
+        }
+    }
+
+    pub fn applies_to(&self, stage: &StageMetrics) -> bool {
+        stage.kind() == self.kind
+            && self
+                .index
+                .is_none_or(|i| stage.name() == format!("{} {i}", self.kind))
+    }
+}
+
+impl FromStr for Injection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stage, fault) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected STAGE:FAULT[@N], but got {s:?}"))?;
+
+        let digits = stage.trim_start_matches(|c: char| !c.is_ascii_digit());
+        let kind = &stage[..stage.len() - digits.len()];
+        if !STAGE_KINDS.contains(&kind) {
+            return Err(format!(
+                "unknown stage {stage:?} (expected one of {}, optionally followed by a number)",
+                STAGE_KINDS.join(", ")
+            ));
+        }
+        let index = if digits.is_empty() {
+            None
+        } else {
+            Some(
+                digits
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid stage number {digits:?}: {e}"))?,
+            )
+        };
+
+        let (fault, after) = match fault.split_once('@') {
+            Some((fault, after)) => (
+                fault,
+                after
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid number of messages {after:?}: {e}"))?,
+            ),
+            None => (fault, 0),
+        };
+        let fault = match fault.split_once('=') {
+            None if fault == "panic" => Fault::Panic,
+            None if fault == "drop" => Fault::Drop(1),
+            None if fault == "close" => Fault::Close,
+            Some(("stall", ms)) => {
+                let ms = ms
+                    .strip_suffix("ms")
+                    .and_then(|ms| ms.parse::<u64>().ok())
+                    .ok_or_else(|| format!("expected a time (e.g., 500ms), but got {ms:?}"))?;
+                Fault::Stall(Duration::from_millis(ms))
+            }
+            Some(("drop", n)) => Fault::Drop(
+                n.parse::<usize>()
+                    .map_err(|e| format!("invalid number of messages to drop {n:?}: {e}"))?,
+            ),
+            _ => {
+                return Err(format!(
+                    "expected panic, stall=TIME, drop[=N], or close, but got {fault:?}"
+                ));
+            }
+        };
+
+        Ok(Injection::new(kind, index, fault, after))
+    }
+}
+
+impl Display for Injection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(index) = self.index {
+            write!(f, "{index}")?;
+        }
+        match self.fault {
+            Fault::Panic => write!(f, ":panic")?,
+            Fault::Stall(d) => write!(f, ":stall={}ms", d.as_millis())?,
+            Fault::Drop(n) => write!(f, ":drop={n}")?,
+            Fault::Close => write!(f, ":close")?,
+        }
+        write!(f, "@{}", self.after)
+    }
+}
+
+/// What a stage should do with the next message it handles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Handle,
+    Drop,
+    Close,
+}
+
+/// The faults to be injected into a single stage, in the order they'll
+/// happen.
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    /// Each fault along with the number of messages to handle before it,
+    /// sorted so the next one is at the end.
+    upcoming: Vec<(usize, Fault)>,
+    dropping: usize,
+}
+
+impl FaultPlan {
+    /// All the faults in `injections` that apply to `stage`.
+    pub fn new(stage: &StageMetrics, injections: &[Injection]) -> Self {
+        let mut plan = Self::default();
+        for injection in injections.iter().filter(|i| i.applies_to(stage)) {
+            plan.add(injection.after, injection.fault);
+        }
+        plan
+    }
+
+    pub fn add(&mut self, after: usize, fault: Fault) {
+        self.upcoming.push((after, fault));
+        self.upcoming.sort_by_key(|&(after, _)| Reverse(after));
+    }
+
+    /// Stages call this before handling each message, passing the number
+    /// of messages they've handled so far, and do whatever it says.
+    /// Panics and stalls happen right here.
+    ///
+    /// A panic is only raised once, so a stage that's restarted after one
+    /// carries on past it.
+    #[inline]
+    pub fn next(&mut self, handled: usize) -> Action {
+        if self.dropping == 0
+            && self
+                .upcoming
+                .last()
+                .is_none_or(|&(after, _)| after > handled)
+        {
+            return Action::Handle;
+        }
+        self.trigger(handled)
+    }
+
+    #[cold]
+    fn trigger(&mut self, handled: usize) -> Action {
+        while let Some(&(after, fault)) = self.upcoming.last()
+            && after <= handled
+        {
+            self.upcoming.pop();
+            match fault {
+                Fault::Panic => panic!("injected panic after {handled} messages"),
+                Fault::Stall(duration) => thread::sleep(duration),
+                Fault::Drop(n) => self.dropping += n,
+                Fault::Close => return Action::Close,
+            }
+        }
+        if self.dropping > 0 {
+            self.dropping -= 1;
+            Action::Drop
+        } else {
+            Action::Handle
+        }
+    }
+}
+
+/// A stage that faults can be injected into.
+pub trait InjectFaults: Sized {
+    fn faults_mut(&mut self) -> &mut FaultPlan;
+
+    /// Inject the given faults (for testing how the pipeline copes).
+    fn with_faults(mut self, faults: FaultPlan) -> Self {
+        *self.faults_mut() = faults;
+        self
+    }
+}
@@ -0,0 +1,35 @@
+use std::{
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossbeam_channel::{Receiver, never, unbounded};
+
+/// The exit status for a process killed by SIGINT, by shell convention.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Start listening for Ctrl-C (SIGINT) and SIGTERM, returning a channel
+/// that gets a message the first time one arrives, so the run can be
+/// wound down in an orderly way.
+///
+/// A second one exits right away, in case winding down is taking too
+/// long (or is stuck). If the handler can't be installed we carry on
+/// without it, and the signals just kill the process as usual.
+pub fn interrupts() -> Receiver<()> {
+    let (sender, receiver) = unbounded();
+    let interrupted = AtomicBool::new(false);
+    let installed = ctrlc::set_handler(move || {
+        if interrupted.swap(true, Ordering::Relaxed) {
+            eprintln!("Interrupted again, so quitting without an estimate.");
+            process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        let _ = sender.send(());
+    });
+    match installed {
+        Ok(()) => receiver,
+        Err(e) => {
+            eprintln!("Couldn't install a handler for Ctrl-C: {e}");
+            never()
+        }
+    }
+}
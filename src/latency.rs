@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+/// A message along with (optionally) when it was created, so whoever
+/// eventually handles it can tell how long it spent in transit.
+///
+/// Reading the clock for every message isn't free, so generators only
+/// fill in `created` when we're actually measuring latency.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stamped<T> {
+    pub value: T,
+    pub created: Option<Instant>,
+}
+
+impl<T> Stamped<T> {
+    /// Wrap `value`, stamping it with the current time if `stamp` is true.
+    pub fn new(value: T, stamp: bool) -> Self {
+        Self {
+            value,
+            created: stamp.then(Instant::now),
+        }
+    }
+}
+
+/// The number of bits of precision we keep below the leading bit of each
+/// value, which gives each power of two `2^SUB_BUCKET_BITS` buckets and
+/// keeps the relative error under about 6%.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const NUM_BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+/// A histogram of durations with log-linear buckets (in the style of HDR
+/// histograms), so it covers everything from nanoseconds to hours in a
+/// fixed amount of space with bounded relative error.
+///
+/// Like [`crate::report::Report`], histograms can be merged in any order,
+/// so each stage can keep its own and we combine them at the end.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: vec![0; NUM_BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket_index(nanos)] += 1;
+        self.count += 1;
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other_count;
+        }
+        self.count += other.count;
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+
+    /// The duration that `quantile` (between 0 and 1) of the recorded
+    /// durations are at or below, rounded up to the top of its bucket.
+    pub fn quantile(&self, quantile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(bucket_upper_bound(index).min(self.max));
+            }
+        }
+        self.max()
+    }
+
+    /// A one line summary like "p50 1.2µs, p99 35µs, max 2.1ms (1000 samples)".
+    pub fn summary(&self) -> String {
+        format!(
+            "p50 {:.2?}, p99 {:.2?}, max {:.2?} ({} samples)",
+            self.quantile(0.5),
+            self.quantile(0.99),
+            self.max(),
+            self.count,
+        )
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - SUB_BUCKET_BITS;
+    let sub_bucket = (value >> shift) as usize & (SUB_BUCKETS - 1);
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let sub_bucket = (index % SUB_BUCKETS) as u64;
+    let lower = (SUB_BUCKETS as u64 + sub_bucket) << shift;
+    lower.saturating_add((1 << shift) - 1)
+}
@@ -1,4 +1,33 @@
+pub mod autoscale;
+pub mod cadence;
+pub mod channels;
+pub mod checkpoint;
+pub mod commands;
+pub mod control;
+pub mod display;
+pub mod error;
+pub mod estimators;
+pub mod faults;
+pub mod interrupt;
+pub mod latency;
+pub mod metrics;
+pub mod monitor;
+pub mod output;
 pub mod point;
+pub mod qmc;
+pub mod reduction;
+pub mod replicates;
+pub mod report;
+pub mod results;
+pub mod rng;
+pub mod roster;
+pub mod strata;
+pub mod supervision;
+pub mod sync;
+pub mod text;
+pub mod trace;
+pub mod watchdog;
+pub mod work;
 
 /// The total number of samples to take.
 pub const NUM_POINTS: usize = 10_000_000;
@@ -8,12 +37,104 @@ pub const NUM_POINTS: usize = 10_000_000;
 /// samples from the circumscribed square. We can use this to
 /// estimate π via:
 ///
-///     num_inside / total_points = (π r^2) / (4 r^2) = π/4
+/// ```text
+/// num_inside / total_points = (π r^2) / (4 r^2) = π/4
+/// ```
 ///
 /// or equivalently
 ///
-///     π = 4 * num_inside / total_points
-///
+/// ```text
+/// π = 4 * num_inside / total_points
+/// ```
 pub fn calculate_estimate(num_inside: usize, total_points: usize) -> f64 {
     4.0 * (num_inside as f64) / (total_points as f64)
 }
+
+/// The volume of the unit ball in `dimensions` dimensions divided by the
+/// power of π in it, π^(dimensions / 2) (rounding down). Going up two
+/// dimensions multiplies the volume by 2π / dimensions, starting from a
+/// point (1) and a line segment (2).
+fn ball_volume_coefficient(dimensions: usize) -> f64 {
+    match dimensions {
+        0 => 1.0,
+        1 => 2.0,
+        d => ball_volume_coefficient(d - 2) * 2.0 / d as f64,
+    }
+}
+
+/// The volume of the ball of radius 1 in `dimensions` dimensions: π in
+/// two dimensions, 4π/3 in three, π²/2 in four, and so on.
+pub fn unit_ball_volume(dimensions: usize) -> f64 {
+    ball_volume_coefficient(dimensions) * std::f64::consts::PI.powi((dimensions / 2) as i32)
+}
+
+/// The generalization of [`calculate_estimate`] to `dimensions` (at
+/// least two) dimensions, given the `fraction` of the points sampled
+/// from the cube [-1, 1]^D that were inside the unit ball, along with
+/// the standard error of `fraction`.
+///
+/// The cube has volume 2^D, so the ball's volume is about 2^D times the
+/// fraction. The ball's volume is c π^k, where k is half the number of
+/// dimensions (rounding down) and c comes from
+/// [`ball_volume_coefficient`], so
+///
+/// ```text
+/// π = (2^D * fraction / c)^(1/k)
+/// ```
+///
+/// Returns the estimate of π and its standard error, which is the
+/// fraction's times how fast the estimate changes with the fraction. In
+/// four or more dimensions that's infinitely fast at a fraction of 0, so
+/// when no points at all were inside the error is only 0 if the
+/// fraction's is too.
+pub fn estimate_pi(fraction: f64, std_error: f64, dimensions: usize) -> (f64, f64) {
+    let power = (dimensions / 2) as f64;
+    let scale = 2f64.powi(dimensions as i32) / ball_volume_coefficient(dimensions);
+    let estimate = (scale * fraction).powf(1.0 / power);
+    if std_error == 0.0 {
+        return (estimate, 0.0);
+    }
+    let slope = scale.powf(1.0 / power) / power * fraction.powf(1.0 / power - 1.0);
+    (estimate, slope * std_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn unit_balls_have_the_known_volumes() {
+        assert_close(unit_ball_volume(2), PI);
+        assert_close(unit_ball_volume(3), 4.0 * PI / 3.0);
+        assert_close(unit_ball_volume(4), PI * PI / 2.0);
+    }
+
+    #[test]
+    fn the_exact_fraction_gives_pi_in_any_dimensions() {
+        for dimensions in 2..=10 {
+            let fraction = unit_ball_volume(dimensions) / 2f64.powi(dimensions as i32);
+            assert_close(estimate_pi(fraction, 0.0, dimensions).0, PI);
+        }
+    }
+
+    #[test]
+    fn the_standard_error_scales_with_the_slope_of_the_estimate() {
+        // π = 4f in two dimensions, 6f in three, and sqrt(32f) in four.
+        assert_close(estimate_pi(PI / 4.0, 0.01, 2).1, 0.04);
+        assert_close(estimate_pi(PI / 6.0, 0.01, 3).1, 0.06);
+        assert_close(estimate_pi(PI * PI / 32.0, 0.01, 4).1, 0.01 * 16.0 / PI);
+    }
+
+    #[test]
+    fn no_points_inside_gives_an_estimate_of_zero_with_no_error() {
+        for dimensions in 2..=10 {
+            assert_eq!(estimate_pi(0.0, 0.0, dimensions), (0.0, 0.0));
+        }
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{sync::lock, text::write_line, trace::Span};
+
+/// When tracing, a `send` or `recv` only gets its own "blocked" span if it
+/// takes at least this long. Shorter waits are just part of the normal
+/// cost of using a channel and would swamp the trace.
+const BLOCKED_SPAN_THRESHOLD: Duration = Duration::from_micros(10);
+
+/// Live counters for a single stage (i.e., a single thread) in a
+/// pipeline. Each stage updates its own counters as it goes, and other
+/// threads (like the display) can read them at any time.
+///
+/// Along with the number of messages handled, we keep track of how long
+/// the stage has spent blocked in `send` (which means whoever is
+/// downstream isn't keeping up, i.e., backpressure) and blocked in `recv`
+/// (which means whoever is upstream isn't keeping up, i.e., starvation).
+/// Whatever's left of the stage's lifetime is time spent actually working.
+///
+/// Timing every `send` and `recv` costs a couple of clock reads per
+/// message, which is a lot compared to the tiny amount of work each
+/// point takes, so it's off until [`StageMetrics::set_timing`] turns it on.
+///
+/// Stages can also record [`Span`]s of activity for a timeline of the run
+/// (see [`crate::trace`]). That's off until [`StageMetrics::set_tracing`]
+/// turns it on.
+///
+/// The stage also keeps track of what it's doing right now (see
+/// [`StageState`]) so that if the pipeline ever gets stuck, the
+/// watchdog can say where.
+///
+/// This is aligned to a (generous) cache line so that stages updating
+/// their own counters don't slow each other down through false sharing.
+#[derive(Debug)]
+#[repr(align(128))]
+pub struct StageMetrics {
+    kind: &'static str,
+    name: String,
+    input: Option<String>,
+    output: Option<String>,
+    state: AtomicU8,
+    messages: AtomicUsize,
+    timing: AtomicBool,
+    tracing: AtomicBool,
+    spans: Mutex<Vec<Span>>,
+    send_nanos: AtomicU64,
+    recv_nanos: AtomicU64,
+    started: OnceLock<Instant>,
+    finished: OnceLock<Instant>,
+}
+
+impl StageMetrics {
+    /// `kind` groups stages that do the same job (e.g., "generator"),
+    /// while `name` identifies this particular one (e.g., "generator 3").
+    pub fn new(kind: &'static str, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            input: None,
+            output: None,
+            state: AtomicU8::new(StageState::NotStarted as u8),
+            messages: AtomicUsize::new(0),
+            timing: AtomicBool::new(false),
+            tracing: AtomicBool::new(false),
+            spans: Mutex::new(Vec::new()),
+            send_nanos: AtomicU64::new(0),
+            recv_nanos: AtomicU64::new(0),
+            started: OnceLock::new(),
+            finished: OnceLock::new(),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name the channel this stage receives on, which should match the
+    /// name of the [`crate::channels::Link`] watching it (if there is one).
+    pub fn with_input(mut self, channel: impl Into<String>) -> Self {
+        self.input = Some(channel.into());
+        self
+    }
+
+    /// Name the channel this stage sends on, as with
+    /// [`StageMetrics::with_input`].
+    pub fn with_output(mut self, channel: impl Into<String>) -> Self {
+        self.output = Some(channel.into());
+        self
+    }
+
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+
+    pub fn output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+
+    pub fn state(&self) -> StageState {
+        StageState::from_u8(self.state.load(Ordering::Relaxed))
+    }
+
+    fn set_state(&self, state: StageState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+    }
+
+    /// Mark the stage as paused (or not) so that no one mistakes it for
+    /// being stuck.
+    pub fn set_paused(&self, paused: bool) {
+        self.set_state(if paused {
+            StageState::Paused
+        } else {
+            StageState::Working
+        });
+    }
+
+    /// Turn measuring the time spent blocked in `send` and `recv` on or off.
+    pub fn set_timing(&self, enabled: bool) {
+        self.timing.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Turn recording spans of activity on or off.
+    pub fn set_tracing(&self, enabled: bool) {
+        self.tracing.store(enabled, Ordering::Relaxed);
+    }
+
+    fn is_tracing(&self) -> bool {
+        self.tracing.load(Ordering::Relaxed)
+    }
+
+    /// Start a span of activity called `name` that ends when the
+    /// returned guard is dropped. This does nothing unless tracing is on.
+    pub fn span(&self, name: &'static str) -> SpanGuard<'_> {
+        SpanGuard {
+            metrics: self,
+            name,
+            start: self.is_tracing().then(Instant::now),
+        }
+    }
+
+    fn record_span(&self, name: &'static str, start: Instant, duration: Duration) {
+        // Only this stage's own thread ever records spans, so this lock is
+        // never contended.
+        lock(&self.spans).push(Span {
+            name,
+            start,
+            duration,
+        });
+    }
+
+    /// All the spans this stage has recorded so far.
+    pub fn spans(&self) -> Vec<Span> {
+        lock(&self.spans).clone()
+    }
+
+    /// Mark the point where the stage starts running. The stage's
+    /// lifetime (and so its working time) is measured from here.
+    pub fn start(&self) {
+        let _ = self.started.set(Instant::now());
+        self.set_state(StageState::Working);
+    }
+
+    /// Mark the point where the stage has finished all its work.
+    pub fn finish(&self) {
+        let _ = self.finished.set(Instant::now());
+        self.set_state(StageState::Finished);
+    }
+
+    /// Record that this stage has handled `n` more messages.
+    pub fn add_messages(&self, n: usize) {
+        self.messages.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn messages(&self) -> usize {
+        self.messages.load(Ordering::Relaxed)
+    }
+
+    /// Run `send` (which should be a call to some channel's `send`),
+    /// counting the time it takes as time blocked sending.
+    pub fn time_send<R>(&self, send: impl FnOnce() -> R) -> R {
+        self.set_state(StageState::Sending);
+        let result = self.time_blocking("blocked on send", &self.send_nanos, send);
+        self.set_state(StageState::Working);
+        result
+    }
+
+    /// Run `recv` (which should be a call to some channel's `recv`),
+    /// counting the time it takes as time blocked receiving.
+    pub fn time_recv<R>(&self, recv: impl FnOnce() -> R) -> R {
+        self.set_state(StageState::Receiving);
+        let result = self.time_blocking("blocked on recv", &self.recv_nanos, recv);
+        self.set_state(StageState::Working);
+        result
+    }
+
+    fn time_blocking<R>(
+        &self,
+        span_name: &'static str,
+        nanos: &AtomicU64,
+        op: impl FnOnce() -> R,
+    ) -> R {
+        let timing = self.timing.load(Ordering::Relaxed);
+        let tracing = self.is_tracing();
+        if !timing && !tracing {
+            return op();
+        }
+        let start = Instant::now();
+        let result = op();
+        let elapsed = start.elapsed();
+        if timing {
+            nanos.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        }
+        if tracing && elapsed >= BLOCKED_SPAN_THRESHOLD {
+            self.record_span(span_name, start, elapsed);
+        }
+        result
+    }
+
+    pub fn send_blocked(&self) -> Duration {
+        Duration::from_nanos(self.send_nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn recv_blocked(&self) -> Duration {
+        Duration::from_nanos(self.recv_nanos.load(Ordering::Relaxed))
+    }
+
+    /// How long the stage has been running (or ran, if it's finished).
+    pub fn lifetime(&self) -> Duration {
+        match (self.started.get(), self.finished.get()) {
+            (Some(&start), Some(&finish)) => finish - start,
+            (Some(start), None) => start.elapsed(),
+            (None, _) => Duration::ZERO,
+        }
+    }
+
+    /// The part of the stage's lifetime it wasn't blocked on a channel.
+    pub fn working(&self) -> Duration {
+        self.lifetime()
+            .saturating_sub(self.send_blocked())
+            .saturating_sub(self.recv_blocked())
+    }
+}
+
+/// The metrics for every stage in a pipeline. Stages can be added at any
+/// time (even partway through a run), and clones of a registry share the
+/// same set of stages.
+#[derive(Debug, Clone, Default)]
+pub struct StageRegistry {
+    stages: Arc<Mutex<Vec<Arc<StageMetrics>>>>,
+}
+
+impl StageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, stage: Arc<StageMetrics>) {
+        lock(&self.stages).push(stage);
+    }
+
+    /// All the stages registered so far, in the order they were added.
+    pub fn snapshot(&self) -> Vec<Arc<StageMetrics>> {
+        lock(&self.stages).clone()
+    }
+
+    /// How many stages of the given kind have been registered and haven't
+    /// finished yet.
+    pub fn running(&self, kind: &str) -> usize {
+        self.snapshot()
+            .iter()
+            .filter(|s| s.kind() == kind && s.state() != StageState::Finished)
+            .count()
+    }
+}
+
+/// What a stage is doing right now. `Sending` and `Receiving` mean the
+/// stage is inside a `send` or `recv`, which is usually quick but is
+/// where it'll be stuck if the pipeline has stalled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageState {
+    NotStarted,
+    Working,
+    Sending,
+    Receiving,
+    Paused,
+    Finished,
+}
+
+impl StageState {
+    fn from_u8(state: u8) -> Self {
+        match state {
+            0 => StageState::NotStarted,
+            1 => StageState::Working,
+            2 => StageState::Sending,
+            3 => StageState::Receiving,
+            4 => StageState::Paused,
+            _ => StageState::Finished,
+        }
+    }
+}
+
+/// A span of activity in progress; see [`StageMetrics::span`].
+pub struct SpanGuard<'a> {
+    metrics: &'a StageMetrics,
+    name: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for SpanGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            self.metrics.record_span(self.name, start, start.elapsed());
+        }
+    }
+}
+
+/// A table with one row per stage showing how many messages it handled,
+/// its throughput, and what fraction of its lifetime it spent working
+/// versus blocked on each side.
+pub fn utilization_table<'a>(stages: impl IntoIterator<Item = &'a StageMetrics>) -> String {
+    let mut table = format!(
+        "{:<16} {:>10} {:>13} {:>9} {:>9} {:>10} {:>10}\n",
+        "stage", "messages", "messages/s", "time", "working", "send wait", "recv wait"
+    );
+    for stage in stages {
+        let lifetime = stage.lifetime();
+        let seconds = lifetime.as_secs_f64();
+        let percent = |d: Duration| {
+            if seconds > 0.0 {
+                100.0 * d.as_secs_f64() / seconds
+            } else {
+                0.0
+            }
+        };
+        let rate = if seconds > 0.0 {
+            stage.messages() as f64 / seconds
+        } else {
+            0.0
+        };
+        write_line(
+            &mut table,
+            format_args!(
+                "{:<16} {:>10} {:>13.0} {:>8.2}s {:>8.1}% {:>9.1}% {:>9.1}%",
+                stage.name(),
+                stage.messages(),
+                rate,
+                seconds,
+                percent(stage.working()),
+                percent(stage.send_blocked()),
+                percent(stage.recv_blocked())
+            ),
+        );
+    }
+    table
+}
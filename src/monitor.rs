@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, bounded};
+
+use crate::{channels::Link, text::write_line};
+
+/// A stage that periodically samples how many messages are waiting in
+/// each of a set of channels, so we can see whether (and how often) any
+/// of them fill up.
+pub struct Monitor {
+    links: Vec<Link>,
+    interval: Duration,
+    stop_receiver: Receiver<()>,
+}
+
+impl Monitor {
+    /// The monitor keeps sampling until the returned sender is dropped,
+    /// which should be done once the rest of the pipeline has finished.
+    pub fn new(links: Vec<Link>, interval: Duration) -> (Self, Sender<()>) {
+        let (stop_sender, stop_receiver) = bounded(0);
+        let monitor = Self {
+            links,
+            interval,
+            stop_receiver,
+        };
+        (monitor, stop_sender)
+    }
+
+    pub fn run(self) -> Vec<Occupancy> {
+        let mut occupancies = self
+            .links
+            .iter()
+            .map(|link| Occupancy::new(link.name(), link.capacity()))
+            .collect::<Vec<_>>();
+
+        loop {
+            for (link, occupancy) in self.links.iter().zip(&mut occupancies) {
+                occupancy.add_sample(link.len());
+            }
+            match self.stop_receiver.recv_timeout(self.interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        occupancies
+    }
+}
+
+/// Summary statistics for the samples of a single channel's occupancy.
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    pub name: String,
+    pub capacity: Option<usize>,
+    pub num_samples: usize,
+    pub min: usize,
+    pub max: usize,
+    pub sum: usize,
+    /// The number of samples where the channel was at capacity.
+    pub num_full: usize,
+}
+
+impl Occupancy {
+    fn new(name: &str, capacity: Option<usize>) -> Self {
+        Self {
+            name: name.to_string(),
+            capacity,
+            num_samples: 0,
+            min: usize::MAX,
+            max: 0,
+            sum: 0,
+            num_full: 0,
+        }
+    }
+
+    fn add_sample(&mut self, len: usize) {
+        self.num_samples += 1;
+        self.min = self.min.min(len);
+        self.max = self.max.max(len);
+        self.sum += len;
+        if self.capacity.is_some_and(|c| len >= c) {
+            self.num_full += 1;
+        }
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.num_samples as f64
+    }
+}
+
+/// A table with one row per channel showing the min/mean/max number of
+/// messages waiting in it, and how often it was full.
+pub fn occupancy_table(occupancies: &[Occupancy]) -> String {
+    let mut table = format!(
+        "{:<16} {:>10} {:>8} {:>8} {:>10} {:>8} {:>8}\n",
+        "channel", "capacity", "samples", "min", "mean", "max", "full"
+    );
+    for o in occupancies {
+        let capacity = o
+            .capacity
+            .map_or_else(|| "unbounded".to_string(), |c| c.to_string());
+        let full = match o.capacity {
+            Some(_) => format!("{:.1}%", 100.0 * o.num_full as f64 / o.num_samples as f64),
+            None => "-".to_string(),
+        };
+        write_line(
+            &mut table,
+            format_args!(
+                "{:<16} {:>10} {:>8} {:>8} {:>10.1} {:>8} {:>8}",
+                o.name,
+                capacity,
+                o.num_samples,
+                o.min,
+                o.mean(),
+                o.max,
+                full
+            ),
+        );
+    }
+    table
+}
@@ -0,0 +1,177 @@
+use std::{
+    sync::Once,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+
+use crate::report::Report;
+
+/// The format progress updates and the final summary are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text (or the dashboard, depending on the display mode).
+    Text,
+    /// One JSON object per line.
+    Json,
+    /// Comma separated values, with a header line first.
+    Csv,
+}
+
+/// Whether a record is one of the updates along the way, the summary at
+/// the end of the run, the summary of a run that was stopped early (which
+/// is a fine estimate, just from fewer points), or a note that the run
+/// failed (in which case the counts only cover the points handled before
+/// the failure and shouldn't be trusted as an estimate).
+///
+/// The rest are estimates made some other way than just counting: from
+/// the cells of a `--strata` run, from the antithetic or control variate
+/// estimators of `--variance-reduction`, from the spread between
+/// `--replicates`, or from the runs combined by `merge`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordKind {
+    Update,
+    Summary,
+    Partial,
+    Invalid,
+    Stratified,
+    Antithetic,
+    ControlVariate,
+    Replicates,
+    Merged,
+}
+
+impl RecordKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            RecordKind::Update => "update",
+            RecordKind::Summary => "summary",
+            RecordKind::Partial => "partial",
+            RecordKind::Invalid => "invalid",
+            RecordKind::Stratified => "stratified",
+            RecordKind::Antithetic => "antithetic",
+            RecordKind::ControlVariate => "control_variate",
+            RecordKind::Replicates => "replicates",
+            RecordKind::Merged => "merged",
+        }
+    }
+}
+
+/// A single structured progress record.
+#[derive(Debug, Clone, Copy)]
+pub struct Record {
+    pub kind: RecordKind,
+    /// Wall clock time the record was made, in milliseconds since the
+    /// Unix epoch.
+    pub timestamp_ms: u128,
+    pub total_points: usize,
+    pub inside_points: usize,
+    pub estimate: f64,
+    pub std_error: f64,
+    pub elapsed: Duration,
+}
+
+impl Record {
+    pub const CSV_HEADER: &str =
+        "kind,timestamp_ms,total_points,inside_points,estimate,std_error,elapsed_s";
+
+    /// The record for `total`, counted from points in `dimensions`
+    /// dimensions.
+    pub fn new(kind: RecordKind, total: &Report, dimensions: usize, elapsed: Duration) -> Self {
+        Self::from_estimate(
+            kind,
+            total,
+            (total.estimate(dimensions), total.standard_error(dimensions)),
+            elapsed,
+        )
+    }
+
+    /// The record for an estimate of π made some other way than from the
+    /// plain count in `total` (which the estimate is still from).
+    pub fn from_estimate(
+        kind: RecordKind,
+        total: &Report,
+        (estimate, std_error): (f64, f64),
+        elapsed: Duration,
+    ) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        Self {
+            kind,
+            timestamp_ms,
+            total_points: total.total_num_points,
+            inside_points: total.num_inside_points,
+            estimate,
+            std_error,
+            elapsed,
+        }
+    }
+
+    /// The record for the end of a successful run, which is `Partial` if
+    /// it was stopped before all `expected_points` were counted.
+    pub fn summary(
+        total: &Report,
+        dimensions: usize,
+        expected_points: usize,
+        elapsed: Duration,
+    ) -> Self {
+        let kind = if total.total_num_points < expected_points {
+            RecordKind::Partial
+        } else {
+            RecordKind::Summary
+        };
+        Self::new(kind, total, dimensions, elapsed)
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"kind":"{}","timestamp_ms":{},"total_points":{},"inside_points":{},"estimate":{},"std_error":{},"elapsed_s":{}}}"#,
+            self.kind.as_str(),
+            self.timestamp_ms,
+            self.total_points,
+            self.inside_points,
+            json_number(self.estimate),
+            json_number(self.std_error),
+            self.elapsed.as_secs_f64(),
+        )
+    }
+
+    pub fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.kind.as_str(),
+            self.timestamp_ms,
+            self.total_points,
+            self.inside_points,
+            self.estimate,
+            self.std_error,
+            self.elapsed.as_secs_f64(),
+        )
+    }
+}
+
+/// Write `record` to stdout in `format`, which mustn't be text. CSV gets
+/// its header before the first record the process writes, however many
+/// runs those records are from (with `--replicates`, say).
+pub fn write_record(format: OutputFormat, record: &Record) {
+    static CSV_HEADER: Once = Once::new();
+    match format {
+        OutputFormat::Json => println!("{}", record.to_json()),
+        OutputFormat::Csv => {
+            CSV_HEADER.call_once(|| println!("{}", Record::CSV_HEADER));
+            println!("{}", record.to_csv());
+        }
+        OutputFormat::Text => unreachable!("text output doesn't use records"),
+    }
+}
+
+/// JSON has no NaN or infinity (which we get for the estimate before
+/// any points have been counted), so those become `null`.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        x.to_string()
+    } else {
+        "null".to_string()
+    }
+}
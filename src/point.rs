@@ -6,15 +6,47 @@ use rand::distr::StandardUniform;
 
 use rand::distr::Distribution;
 
-#[derive(Debug, Default)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
+/// A point in the cube [-1, 1]^D, which is the square [-1, 1]² unless
+/// we say otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point<const D: usize = 2> {
+    pub coordinates: [f64; D],
 }
 
 impl Point {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self {
+            coordinates: [x, y],
+        }
+    }
+
+    pub fn x(&self) -> f64 {
+        self.coordinates[0]
+    }
+
+    pub fn y(&self) -> f64 {
+        self.coordinates[1]
+    }
+
+    #[deprecated = "use `inside_unit_ball`, which works in any number of dimensions"]
     pub fn inside_unit_circle(&self) -> bool {
-        self.x * self.x + self.y * self.y <= 1.0
+        self.inside_unit_ball()
+    }
+}
+
+impl<const D: usize> Point<D> {
+    /// Whether the point is inside the ball of radius 1 centered at the
+    /// origin (the unit circle in two dimensions).
+    pub fn inside_unit_ball(&self) -> bool {
+        self.coordinates.iter().map(|c| c * c).sum::<f64>() <= 1.0
+    }
+}
+
+impl<const D: usize> Default for Point<D> {
+    fn default() -> Self {
+        Self {
+            coordinates: [0.0; D],
+        }
     }
 }
 
@@ -23,13 +55,14 @@ impl Point {
 /// `let pt: Point = rng.random()`, and it'll figure out what
 /// _kind_ of thing we want (from the type of `pt`) and then call the
 /// "right" version of `sample` (namely this one). How the pieces
-/// connect are somewhat complex and subtle, but it does work.
-impl Distribution<Point> for StandardUniform {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Point {
+/// connect are somewhat complex and subtle, but it does work. The same
+/// goes for points in any number of dimensions, like
+/// `let pt: Point<5> = rng.random()`.
+impl<const D: usize> Distribution<Point<D>> for StandardUniform {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Point<D> {
         let dist = Uniform::new(-1.0, 1.0).unwrap();
-        let x = rng.sample(dist);
-        let y = rng.sample(dist);
-
-        Point { x, y }
+        Point {
+            coordinates: std::array::from_fn(|_| rng.sample(dist)),
+        }
     }
 }
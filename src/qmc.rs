@@ -0,0 +1,187 @@
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+/// A low-discrepancy ("quasi-random") sequence of points in the unit
+/// square. The points fill the square far more evenly than random ones,
+/// so the error in the estimate shrinks roughly like 1/n rather than
+/// 1/sqrt(n).
+///
+/// Points are returned as pairs of fractions scaled by 2^64, which keeps
+/// Sobol and R2 points exact however far into the sequence we get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sequence {
+    /// The radical inverses of the index in bases 2 and 3.
+    Halton,
+    /// The Sobol sequence, using the first two Sobol dimensions.
+    Sobol,
+    /// Roberts' R2 sequence: multiples of (1/g, 1/g²) mod 1, where g is
+    /// the plastic number.
+    R2,
+}
+
+/// 2^64 / g and 2^64 / g², where g ≈ 1.3247 is the plastic number (the
+/// real root of x³ = x + 1).
+const R2_STEPS: [u64; 2] = [0xC13F_A9A9_02A6_328F, 0x91E1_0DA5_C79E_7B1D];
+
+/// The direction numbers for the second Sobol dimension, from the
+/// primitive polynomial x + 1. (The first dimension's are just the powers
+/// of two, which makes it the base 2 radical inverse.)
+const SOBOL_DIRECTIONS: [u64; 64] = sobol_directions();
+
+const fn sobol_directions() -> [u64; 64] {
+    let mut directions = [0; 64];
+    directions[0] = 1 << 63;
+    let mut i = 1;
+    while i < 64 {
+        directions[i] = directions[i - 1] ^ (directions[i - 1] >> 1);
+        i += 1;
+    }
+    directions
+}
+
+/// 2^64 as a float, for turning fractions into scaled integers.
+const TWO_TO_THE_64: f64 = 18_446_744_073_709_551_616.0;
+
+impl Sequence {
+    /// The point with the given index.
+    pub fn point(self, index: u64) -> [u64; 2] {
+        match self {
+            Sequence::Halton => [
+                index.reverse_bits(),
+                (radical_inverse(3, index) * TWO_TO_THE_64) as u64,
+            ],
+            Sequence::Sobol => {
+                let mut y = 0;
+                let mut bits = index;
+                while bits != 0 {
+                    y ^= SOBOL_DIRECTIONS[bits.trailing_zeros() as usize];
+                    bits &= bits - 1;
+                }
+                [index.reverse_bits(), y]
+            }
+            Sequence::R2 => [
+                index.wrapping_mul(R2_STEPS[0]),
+                index.wrapping_mul(R2_STEPS[1]),
+            ],
+        }
+    }
+}
+
+/// The digits of `n` in `base`, reflected about the decimal point.
+fn radical_inverse(base: u64, mut n: u64) -> f64 {
+    let mut inverse = 0.0;
+    let mut scale = 1.0 / base as f64;
+    while n > 0 {
+        inverse += (n % base) as f64 * scale;
+        n /= base;
+        scale /= base as f64;
+    }
+    inverse
+}
+
+/// A [`Sequence`] with every point moved by the same random shift
+/// (wrapping around the edges of the square), which is known as a
+/// Cranley–Patterson rotation. Unlike scrambling, which shuffles the
+/// digits of each point differently, this moves every point the same way.
+///
+/// On its own a sequence always gives the same points, so there's no way
+/// to tell how far off its estimate is. Shifted copies with independent
+/// shifts give independent estimates that are each unbiased, and the
+/// spread between them measures the error. Sobol points are shifted
+/// digitwise (by XOR), which keeps their structure intact; the others are
+/// shifted mod 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Shifted {
+    sequence: Sequence,
+    shift: [u64; 2],
+}
+
+impl Shifted {
+    pub fn new(sequence: Sequence, seed: u64) -> Self {
+        Self {
+            sequence,
+            shift: StdRng::seed_from_u64(seed).random(),
+        }
+    }
+
+    /// The point with the given index.
+    pub fn point(&self, index: u64) -> [u64; 2] {
+        let [x, y] = self.sequence.point(index);
+        let [dx, dy] = self.shift;
+        match self.sequence {
+            Sequence::Sobol => [x ^ dx, y ^ dy],
+            Sequence::Halton | Sequence::R2 => [x.wrapping_add(dx), y.wrapping_add(dy)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first few points of `sequence` as fractions.
+    fn points(sequence: Sequence, count: u64) -> Vec<[f64; 2]> {
+        (0..count)
+            .map(|index| sequence.point(index).map(|x| x as f64 / TWO_TO_THE_64))
+            .collect()
+    }
+
+    #[test]
+    fn sobol_starts_with_the_standard_points() {
+        assert_eq!(
+            points(Sequence::Sobol, 8),
+            [
+                [0.0, 0.0],
+                [0.5, 0.5],
+                [0.25, 0.75],
+                [0.75, 0.25],
+                [0.125, 0.625],
+                [0.625, 0.125],
+                [0.375, 0.375],
+                [0.875, 0.875],
+            ]
+        );
+    }
+
+    #[test]
+    fn halton_starts_with_the_standard_points() {
+        let expected = [
+            [0.0, 0.0],
+            [0.5, 1.0 / 3.0],
+            [0.25, 2.0 / 3.0],
+            [0.75, 1.0 / 9.0],
+            [0.125, 4.0 / 9.0],
+        ];
+        for (point, expected) in points(Sequence::Halton, 5).iter().zip(expected) {
+            assert!((point[0] - expected[0]).abs() < 1e-15, "{point:?}");
+            assert!((point[1] - expected[1]).abs() < 1e-15, "{point:?}");
+        }
+    }
+
+    #[test]
+    fn r2_steps_by_the_inverse_powers_of_the_plastic_number() {
+        let plastic = 1.324_717_957_244_746;
+        let steps = [1.0 / plastic, 1.0 / (plastic * plastic)];
+        for (index, point) in points(Sequence::R2, 5).iter().enumerate() {
+            for (coordinate, step) in point.iter().zip(steps) {
+                let expected = (index as f64 * step).fract();
+                assert!((coordinate - expected).abs() < 1e-12, "{point:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_shifts_keep_the_points_in_distinct_cells() {
+        // The first 2^k Sobol points have one point in each of the 2^k
+        // rows and each of the 2^k columns of a 2^k by 2^k grid, and a
+        // digitwise shift keeps it that way.
+        let shifted = Shifted::new(Sequence::Sobol, 7);
+        let cells = (0..16)
+            .map(|index| shifted.point(index).map(|x| x >> 60))
+            .collect::<Vec<_>>();
+        for axis in 0..2 {
+            let mut seen = cells.iter().map(|cell| cell[axis]).collect::<Vec<_>>();
+            seen.sort_unstable();
+            assert_eq!(seen, (0..16).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, unbounded};
+
+use crate::{
+    error::PipelineError,
+    faults::{Action, FaultPlan, InjectFaults},
+    metrics::StageMetrics,
+    report::Report,
+};
+
+/// The longest an aggregator holds on to reports waiting for the rest of
+/// its `fan_in`. Without a limit, a report flushed by a manager that's
+/// pausing (or answering a status query) could sit in an aggregator
+/// whose other senders have nothing to say until the end of the run.
+pub const MAX_HOLD: Duration = Duration::from_millis(50);
+
+/// An intermediate stage that sits between some set of senders (point
+/// managers or other aggregators) and a single downstream receiver.
+/// It merges the reports it receives and only forwards one combined
+/// report for every `fan_in` that arrive (or once it has held on to
+/// them for [`MAX_HOLD`]), so each level of aggregators cuts the number
+/// of messages the next level has to handle by a factor of up to
+/// `fan_in`.
+pub struct Aggregator {
+    fan_in: usize,
+    sender: Sender<Report>,
+    receiver: Receiver<Report>,
+    parent: Sender<Report>,
+    metrics: Arc<StageMetrics>,
+    faults: FaultPlan,
+}
+
+impl Aggregator {
+    pub fn new(fan_in: usize, parent: Sender<Report>, metrics: Arc<StageMetrics>) -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            fan_in,
+            sender,
+            receiver,
+            parent,
+            metrics,
+            faults: FaultPlan::default(),
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<StageMetrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    pub fn get_sender(&self) -> Sender<Report> {
+        self.sender.clone()
+    }
+
+    pub fn get_receiver(&self) -> Receiver<Report> {
+        self.receiver.clone()
+    }
+
+    pub fn run(mut self) -> Result<(), PipelineError> {
+        // As with the `Reporter`, we have to drop our own copy of the
+        // sender or the channel will never close.
+        drop(self.sender);
+
+        let mut pending = Report::default();
+        let mut num_pending = 0;
+        let mut held_since = None;
+        let forward = |report| {
+            self.metrics
+                .time_send(|| self.parent.send(report))
+                .map_err(|_| PipelineError::disconnected(self.metrics.name(), "reports"))
+        };
+
+        self.metrics.start();
+        loop {
+            let received = self.metrics.time_recv(|| match held_since {
+                Some(since) => self.receiver.recv_deadline(since + MAX_HOLD),
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            });
+            let report = match received {
+                Ok(report) => report,
+                Err(RecvTimeoutError::Timeout) => {
+                    forward(pending)?;
+                    pending = Report::default();
+                    num_pending = 0;
+                    held_since = None;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            match self.faults.next(self.metrics.messages()) {
+                Action::Handle => {}
+                Action::Drop => continue,
+                Action::Close => {
+                    self.metrics.finish();
+                    return Ok(());
+                }
+            }
+            let _span = self.metrics.span("merging a report");
+            self.metrics.add_messages(1);
+            pending += report;
+            num_pending += 1;
+            held_since.get_or_insert_with(Instant::now);
+
+            if num_pending == self.fan_in {
+                forward(pending)?;
+                pending = Report::default();
+                num_pending = 0;
+                held_since = None;
+            }
+        }
+
+        if !pending.is_empty() {
+            forward(pending)?;
+        }
+        self.metrics.finish();
+        Ok(())
+    }
+}
+
+impl InjectFaults for Aggregator {
+    fn faults_mut(&mut self) -> &mut FaultPlan {
+        &mut self.faults
+    }
+}
+
+/// Build a reduction tree with `num_leaves` leaves (typically the point
+/// managers) that feeds into `root`, where no node has more than `fan_in`
+/// children.
+///
+/// This returns one sender per leaf along with all the aggregators that
+/// need to be run (each on its own thread) for reports to make it to
+/// `root`. If `fan_in` is less than 2, or there are few enough leaves
+/// that they can all talk to `root` directly, there are no aggregators
+/// and every leaf just gets a clone of `root` (i.e., "flat" reduction).
+pub fn build_tree(
+    root: &Sender<Report>,
+    num_leaves: usize,
+    fan_in: usize,
+) -> (Vec<Sender<Report>>, Vec<Aggregator>) {
+    // Work out how many aggregators we need at each level, from just
+    // above the leaves up to just below the root.
+    let mut level_sizes = Vec::new();
+    if fan_in >= 2 {
+        let mut width = num_leaves;
+        while width > fan_in {
+            width = width.div_ceil(fan_in);
+            level_sizes.push(width);
+        }
+    }
+
+    // Then build the levels top down, connecting each node to one of the
+    // nodes in the level above it.
+    let mut aggregators = Vec::new();
+    let mut parents = vec![root.clone()];
+    for &size in level_sizes.iter().rev() {
+        let level = (0..size)
+            .map(|i| {
+                let name = format!("aggregator {}", aggregators.len() + i);
+                Aggregator::new(
+                    fan_in,
+                    parents[i % parents.len()].clone(),
+                    Arc::new(StageMetrics::new("aggregator", name.clone()).with_input(name)),
+                )
+            })
+            .collect::<Vec<_>>();
+        parents = level.iter().map(Aggregator::get_sender).collect();
+        aggregators.extend(level);
+    }
+
+    let leaves = (0..num_leaves)
+        .map(|i| parents[i % parents.len()].clone())
+        .collect();
+
+    (leaves, aggregators)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn a_lone_report_is_forwarded_without_waiting_for_the_rest() {
+        let (parent, reports) = unbounded();
+        let aggregator = Aggregator::new(
+            4,
+            parent,
+            Arc::new(StageMetrics::new("aggregator", "aggregator 0")),
+        );
+        let sender = aggregator.get_sender();
+        let aggregator = thread::spawn(move || aggregator.run());
+
+        sender.send(Report::new(3, 4)).unwrap();
+        assert_eq!(
+            reports.recv_timeout(10 * MAX_HOLD),
+            Ok(Report::new(3, 4)),
+            "the report should be forwarded while its sender is still open"
+        );
+
+        drop(sender);
+        aggregator.join().unwrap().unwrap();
+    }
+}
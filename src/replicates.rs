@@ -0,0 +1,72 @@
+use crate::report::{Report, Z_95};
+
+/// The two-sided 95% quantiles of Student's t distribution for 1 to 30
+/// degrees of freedom. With more than that the normal distribution is
+/// close enough.
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+/// The results of independent replicates of a run, each from its own
+/// seed, which together give an estimate whose error is measured by how
+/// much the replicates differ from each other.
+///
+/// [`Report::standard_error`] assumes every point is independent, which
+/// isn't true of quasi-random points (they're spread out evenly on
+/// purpose), so for those it's far too pessimistic. The spread between
+/// replicates makes no such assumption.
+#[derive(Debug, Default, Clone)]
+pub struct Replicates {
+    estimates: Vec<f64>,
+}
+
+impl Replicates {
+    /// Add a replicate's report, from points in `dimensions` dimensions.
+    pub fn add(&mut self, report: Report, dimensions: usize) {
+        self.estimates.push(report.estimate(dimensions));
+    }
+
+    pub fn len(&self) -> usize {
+        self.estimates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.estimates.is_empty()
+    }
+
+    /// The mean of the replicates' estimates.
+    pub fn estimate(&self) -> f64 {
+        self.estimates.iter().sum::<f64>() / self.len() as f64
+    }
+
+    /// The standard error of [`Replicates::estimate`], from the sample
+    /// variance of the replicates' estimates. This needs at least two
+    /// replicates (it's NaN otherwise).
+    pub fn standard_error(&self) -> f64 {
+        let n = self.len() as f64;
+        let mean = self.estimate();
+        let variance = self
+            .estimates
+            .iter()
+            .map(|estimate| (estimate - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+        (variance / n).sqrt()
+    }
+
+    /// A 95% confidence interval for π, using Student's t distribution
+    /// since there are usually only a handful of replicates.
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        let degrees_of_freedom = self.len().saturating_sub(1);
+        let quantile = degrees_of_freedom
+            .checked_sub(1)
+            .and_then(|i| T_95.get(i))
+            .copied()
+            .unwrap_or(Z_95);
+        let estimate = self.estimate();
+        let margin = quantile * self.standard_error();
+        (estimate - margin, estimate + margin)
+    }
+}
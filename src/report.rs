@@ -0,0 +1,116 @@
+use std::{
+    iter::Sum,
+    ops::{Add, AddAssign},
+};
+
+use crate::estimate_pi;
+
+/// The z-score for a two-sided 95% confidence interval.
+pub(crate) const Z_95: f64 = 1.96;
+
+/// A summary of some number of classified points: how many of them
+/// were inside the unit circle (or ball) out of how many were looked at.
+///
+/// Reports form a commutative monoid under [`Report::merge`] with
+/// [`Report::default`] (no points at all) as the identity. That means
+/// reports can be combined in any order and any grouping and still
+/// give the same totals, which is what lets us reduce them through a
+/// tree of aggregators (see [`crate::reduction`]) instead of funnelling
+/// every one of them into a single reporter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub num_inside_points: usize,
+    pub total_num_points: usize,
+}
+
+impl Report {
+    pub fn new(num_inside_points: usize, total_num_points: usize) -> Self {
+        Report {
+            num_inside_points,
+            total_num_points,
+        }
+    }
+
+    /// Combine two reports into one that covers the points of both.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Report {
+            num_inside_points: self.num_inside_points + other.num_inside_points,
+            total_num_points: self.total_num_points + other.total_num_points,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_num_points == 0
+    }
+
+    /// The fraction of the points that were inside.
+    pub fn fraction_inside(&self) -> f64 {
+        self.num_inside_points as f64 / self.total_num_points as f64
+    }
+
+    /// The standard error of [`Report::fraction_inside`]. Each point is
+    /// an independent Bernoulli trial with some success probability p, so
+    /// the fraction p̂ has standard error sqrt(p̂(1 - p̂)/n).
+    pub fn fraction_standard_error(&self) -> f64 {
+        let p = self.fraction_inside();
+        (p * (1.0 - p) / self.total_num_points as f64).sqrt()
+    }
+
+    /// The estimate of π based on just the points in this report, which
+    /// were sampled in `dimensions` dimensions (see
+    /// [`crate::estimate_pi`]).
+    pub fn estimate(&self, dimensions: usize) -> f64 {
+        estimate_pi(self.fraction_inside(), 0.0, dimensions).0
+    }
+
+    /// The standard error of [`Report::estimate`]. In two dimensions the
+    /// estimate is 4p̂, so this is 4·sqrt(p̂(1 - p̂)/n).
+    pub fn standard_error(&self, dimensions: usize) -> f64 {
+        estimate_pi(
+            self.fraction_inside(),
+            self.fraction_standard_error(),
+            dimensions,
+        )
+        .1
+    }
+
+    /// An approximate 95% confidence interval for π.
+    pub fn confidence_interval(&self, dimensions: usize) -> (f64, f64) {
+        let estimate = self.estimate(dimensions);
+        let margin = Z_95 * self.standard_error(dimensions);
+        (estimate - margin, estimate + margin)
+    }
+
+    /// The estimate of the volume of the unit ball in `dimensions`
+    /// dimensions: the fraction of the cube [-1, 1]^D it takes up, times
+    /// the cube's volume of 2^D.
+    pub fn volume(&self, dimensions: usize) -> f64 {
+        2f64.powi(dimensions as i32) * self.fraction_inside()
+    }
+
+    /// The standard error of [`Report::volume`].
+    pub fn volume_standard_error(&self, dimensions: usize) -> f64 {
+        2f64.powi(dimensions as i32) * self.fraction_standard_error()
+    }
+}
+
+impl Add for Report {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.merge(other)
+    }
+}
+
+impl AddAssign for Report {
+    fn add_assign(&mut self, other: Self) {
+        *self = self.merge(other);
+    }
+}
+
+impl Sum for Report {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Report::default(), Report::merge)
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{checkpoint::parse_number, report::Report, rng::PointRng};
+
+/// Everything worth keeping about a finished run: what it counted, where
+/// its points came from, and how it was set up. Results from separate
+/// runs (on other machines, say) can be combined with [`merge`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunResult {
+    pub counted: Report,
+    /// The random number generator the points came from.
+    pub rng: PointRng,
+    /// How many dimensions the points had.
+    pub dimensions: usize,
+    /// The seed, for seeded runs. Unseeded runs get their points from
+    /// fresh entropy, so they can't overlap with any other run.
+    pub seed: Option<u64>,
+    /// The indices of the points the run generated (or skipped over).
+    pub points: Range<usize>,
+    /// The indices of the generator streams the run used.
+    pub streams: Range<usize>,
+    pub generators: usize,
+    pub managers: usize,
+    pub fan_in: usize,
+    pub elapsed: Duration,
+}
+
+impl RunResult {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read results {}: {e}", path.display()))?
+            .parse()
+            .map_err(|e| format!("bad results {}: {e}", path.display()))
+    }
+
+    /// Whether this run and `other` could have generated some of the
+    /// same points, and if so which (e.g., "points 0..10 from seed 1").
+    fn overlap(&self, other: &Self) -> Option<String> {
+        let seed = self.seed?;
+        if other.seed != Some(seed) || other.rng != self.rng {
+            return None;
+        }
+        let (what, ours, theirs) = if self.rng.has_generator_streams() {
+            ("streams", &self.streams, &other.streams)
+        } else {
+            ("points", &self.points, &other.points)
+        };
+        let overlap = ours.start.max(theirs.start)..ours.end.min(theirs.end);
+        (!overlap.is_empty())
+            .then(|| format!("{what} {}..{} from seed {seed}", overlap.start, overlap.end))
+    }
+}
+
+impl Display for RunResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "total_points={}", self.counted.total_num_points)?;
+        writeln!(f, "inside_points={}", self.counted.num_inside_points)?;
+        writeln!(f, "rng={}", self.rng)?;
+        writeln!(f, "dimensions={}", self.dimensions)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed={seed}")?;
+        }
+        writeln!(f, "first_point={}", self.points.start)?;
+        writeln!(f, "end_point={}", self.points.end)?;
+        writeln!(f, "first_stream={}", self.streams.start)?;
+        writeln!(f, "end_stream={}", self.streams.end)?;
+        writeln!(f, "generators={}", self.generators)?;
+        writeln!(f, "managers={}", self.managers)?;
+        writeln!(f, "fan_in={}", self.fan_in)?;
+        writeln!(f, "elapsed_s={}", self.elapsed.as_secs_f64())
+    }
+}
+
+impl FromStr for RunResult {
+    type Err = String;
+
+    /// Parses the `key=value` lines written by `Display`, in any order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut total_points = None;
+        let mut inside_points = None;
+        let mut rng = None;
+        let mut dimensions = None;
+        let mut seed = None;
+        let mut first_point = None;
+        let mut end_point = None;
+        let mut first_stream = None;
+        let mut end_stream = None;
+        let mut generators = None;
+        let mut managers = None;
+        let mut fan_in = None;
+        let mut elapsed = None;
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got {line:?}"))?;
+            let field = match key {
+                "total_points" => &mut total_points,
+                "inside_points" => &mut inside_points,
+                "first_point" => &mut first_point,
+                "end_point" => &mut end_point,
+                "first_stream" => &mut first_stream,
+                "end_stream" => &mut end_stream,
+                "generators" => &mut generators,
+                "managers" => &mut managers,
+                "fan_in" => &mut fan_in,
+                "dimensions" => &mut dimensions,
+                "rng" => {
+                    rng = Some(value.parse()?);
+                    continue;
+                }
+                "seed" => {
+                    seed = Some(parse_number(key, value)?);
+                    continue;
+                }
+                "elapsed_s" => {
+                    let seconds = parse_number(key, value)?;
+                    elapsed = Some(
+                        Duration::try_from_secs_f64(seconds)
+                            .map_err(|e| format!("bad elapsed_s {value:?}: {e}"))?,
+                    );
+                    continue;
+                }
+                _ => return Err(format!("unknown key {key:?}")),
+            };
+            *field = Some(parse_number(key, value)?);
+        }
+
+        let missing = |key| format!("missing {key}");
+        let counted = Report::new(
+            inside_points.ok_or_else(|| missing("inside_points"))?,
+            total_points.ok_or_else(|| missing("total_points"))?,
+        );
+        if counted.num_inside_points > counted.total_num_points {
+            return Err("more points inside the circle than in total".to_string());
+        }
+        Ok(RunResult {
+            counted,
+            rng: rng.ok_or_else(|| missing("rng"))?,
+            // Results from before points could have more than two
+            // dimensions don't say how many they had.
+            dimensions: dimensions.unwrap_or(2),
+            seed,
+            points: first_point.ok_or_else(|| missing("first_point"))?
+                ..end_point.ok_or_else(|| missing("end_point"))?,
+            // Results from before xoshiro don't say anything about streams,
+            // and didn't use any.
+            streams: first_stream.unwrap_or(0)..end_stream.unwrap_or(0),
+            generators: generators.ok_or_else(|| missing("generators"))?,
+            managers: managers.ok_or_else(|| missing("managers"))?,
+            fan_in: fan_in.ok_or_else(|| missing("fan_in"))?,
+            elapsed: elapsed.ok_or_else(|| missing("elapsed_s"))?,
+        })
+    }
+}
+
+/// Add up the counts from several runs, after checking that they all
+/// had points with the same number of dimensions and that no two of them
+/// share any points. Two runs from the same seed that generated some of
+/// the same points would count those points twice, which would make the
+/// estimate look more precise than it is.
+pub fn merge(results: &[(PathBuf, RunResult)]) -> Result<Report, String> {
+    for (i, (path, result)) in results.iter().enumerate() {
+        for (other_path, other) in &results[i + 1..] {
+            if result.dimensions != other.dimensions {
+                return Err(format!(
+                    "{} has points in {} dimensions but {} has them in {}",
+                    path.display(),
+                    result.dimensions,
+                    other_path.display(),
+                    other.dimensions,
+                ));
+            }
+            if let Some(overlap) = result.overlap(other) {
+                return Err(format!(
+                    "{} and {} both used {overlap}",
+                    path.display(),
+                    other_path.display(),
+                ));
+            }
+        }
+    }
+    Ok(results.iter().map(|(_, result)| result.counted).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(rng: PointRng, seed: u64, points: Range<usize>, streams: Range<usize>) -> RunResult {
+        RunResult {
+            counted: Report::new(points.len() * 3 / 4, points.len()),
+            rng,
+            dimensions: 2,
+            seed: Some(seed),
+            points,
+            streams,
+            generators: 1,
+            managers: 1,
+            fan_in: 0,
+            elapsed: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn runs_overlap_if_they_share_points_from_the_same_seed() {
+        let first = result(PointRng::Philox, 1, 0..10, 0..1);
+        let second = result(PointRng::Philox, 1, 5..20, 1..2);
+        assert_eq!(
+            first.overlap(&second).as_deref(),
+            Some("points 5..10 from seed 1")
+        );
+        let later = result(PointRng::Philox, 1, 10..20, 0..1);
+        assert_eq!(first.overlap(&later), None);
+        let reseeded = result(PointRng::Philox, 2, 0..10, 0..1);
+        assert_eq!(first.overlap(&reseeded), None);
+        let other_rng = result(PointRng::Chacha12, 1, 0..10, 0..1);
+        assert_eq!(first.overlap(&other_rng), None);
+        let unseeded = RunResult {
+            seed: None,
+            ..first.clone()
+        };
+        assert_eq!(unseeded.overlap(&unseeded), None);
+    }
+
+    #[test]
+    fn xoshiro_runs_overlap_if_they_share_streams_rather_than_points() {
+        let first = result(PointRng::Xoshiro, 5, 0..10, 0..3);
+        let same_points = result(PointRng::Xoshiro, 5, 0..10, 3..6);
+        assert_eq!(first.overlap(&same_points), None);
+        let same_streams = result(PointRng::Xoshiro, 5, 10..20, 2..4);
+        assert_eq!(
+            first.overlap(&same_streams).as_deref(),
+            Some("streams 2..3 from seed 5")
+        );
+    }
+
+    #[test]
+    fn merging_adds_up_the_counts() {
+        let results = [
+            ("a".into(), result(PointRng::Philox, 1, 0..40, 0..1)),
+            ("b".into(), result(PointRng::Philox, 1, 40..100, 0..1)),
+        ];
+        assert_eq!(merge(&results), Ok(Report::new(75, 100)));
+    }
+
+    #[test]
+    fn merging_rejects_overlapping_runs() {
+        let results = [
+            ("a".into(), result(PointRng::Philox, 1, 0..40, 0..1)),
+            ("b".into(), result(PointRng::Philox, 1, 30..100, 0..1)),
+        ];
+        assert_eq!(
+            merge(&results),
+            Err("a and b both used points 30..40 from seed 1".to_string())
+        );
+    }
+
+    #[test]
+    fn results_from_before_streams_still_load() {
+        let result: RunResult = "total_points=100\n\
+            inside_points=78\n\
+            rng=chacha12\n\
+            seed=7\n\
+            first_point=0\n\
+            end_point=100\n\
+            generators=2\n\
+            managers=1\n\
+            fan_in=0\n\
+            elapsed_s=0.5\n"
+            .parse()
+            .unwrap();
+        assert_eq!(result.rng, PointRng::Chacha12);
+        assert_eq!(result.streams, 0..0);
+        assert_eq!(result.dimensions, 2);
+        assert_eq!(result.points, 0..100);
+    }
+}
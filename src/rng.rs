@@ -0,0 +1,270 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{
+    point::Point,
+    qmc::{Sequence, Shifted},
+};
+
+/// How the generators turn point indices into points.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PointRng {
+    /// ChaCha12, with a separate stream for each batch of points keyed
+    /// by the seed and the index of the batch's first point. Seeded runs
+    /// give the same points however many generators there are, as long
+    /// as the batches start in the same places.
+    #[default]
+    Chacha12,
+    /// Philox4x32-10, a counter-based generator: each point is computed
+    /// from the seed and its own index alone, so seeded runs give
+    /// exactly the same points however the work is split up, whether
+    /// between threads, between runs, or around a resume.
+    Philox,
+    /// Xoshiro256++, with the single stream it generates from the seed
+    /// split into substreams 2^128 numbers apart, one for each generator.
+    /// A generator would have to use up 2^128 numbers before running into
+    /// the next one's substream, so no two of them can share any points.
+    /// Which points get which index depends on how the generators share
+    /// out the work.
+    Xoshiro,
+    /// The quasi-random Halton sequence, shifted at random by the seed
+    /// (see [`Shifted`]).
+    Halton,
+    /// The quasi-random Sobol sequence, shifted at random by the seed.
+    Sobol,
+    /// The quasi-random R2 sequence, shifted at random by the seed.
+    R2,
+}
+
+impl PointRng {
+    /// The points for a generator that's the `stream`th to generate them
+    /// from `seed`.
+    pub fn stream(self, seed: u64, stream: usize) -> PointStream {
+        let source = match self {
+            PointRng::Chacha12 => Source::Batch(Box::new(batch_rng(seed, 0))),
+            PointRng::Philox => Source::Counter(Philox::new(seed)),
+            PointRng::Xoshiro => {
+                let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+                for _ in 0..stream {
+                    rng.jump();
+                }
+                Source::Jumped(rng)
+            }
+            PointRng::Halton => Source::Sequence(Shifted::new(Sequence::Halton, seed)),
+            PointRng::Sobol => Source::Sequence(Shifted::new(Sequence::Sobol, seed)),
+            PointRng::R2 => Source::Sequence(Shifted::new(Sequence::R2, seed)),
+        };
+        PointStream { seed, source }
+    }
+
+    /// Whether each generator has a stream of its own, so that two runs
+    /// from the same seed share points if they share any stream (rather
+    /// than any point indices).
+    pub fn has_generator_streams(self) -> bool {
+        self == PointRng::Xoshiro
+    }
+
+    /// Whether this can make points in `dimensions` dimensions. The
+    /// quasi-random sequences only come in two.
+    pub fn supports_dimensions(self, dimensions: usize) -> bool {
+        match self {
+            PointRng::Chacha12 | PointRng::Philox | PointRng::Xoshiro => true,
+            PointRng::Halton | PointRng::Sobol | PointRng::R2 => dimensions == 2,
+        }
+    }
+}
+
+impl Display for PointRng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self
+            .to_possible_value()
+            .expect("every kind of rng has a name");
+        f.write_str(value.get_name())
+    }
+}
+
+impl FromStr for PointRng {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, false).map_err(|_| format!("unknown rng {s:?}"))
+    }
+}
+
+/// Where one generator gets its points from.
+#[derive(Debug)]
+pub struct PointStream {
+    seed: u64,
+    source: Source,
+}
+
+#[derive(Debug)]
+enum Source {
+    Batch(Box<StdRng>),
+    Counter(Philox),
+    Jumped(Xoshiro256PlusPlus),
+    Sequence(Shifted),
+}
+
+impl PointStream {
+    /// Get ready to generate the batch of points starting at index
+    /// `start`.
+    pub fn start_batch(&mut self, start: usize) {
+        if let Source::Batch(rng) = &mut self.source {
+            **rng = batch_rng(self.seed, start);
+        }
+    }
+
+    /// The point with the given index, which should be the next one in
+    /// the batch.
+    ///
+    /// # Panics
+    ///
+    /// If this is a quasi-random sequence and `D` isn't 2 (see
+    /// [`PointRng::supports_dimensions`]).
+    pub fn point<const D: usize>(&mut self, index: usize) -> Point<D> {
+        match &mut self.source {
+            Source::Batch(rng) => rng.random(),
+            Source::Counter(philox) => philox.point(index as u64),
+            Source::Jumped(rng) => rng.random(),
+            Source::Sequence(sequence) => {
+                assert_eq!(D, 2, "quasi-random sequences are two-dimensional");
+                let bits = sequence.point(index as u64);
+                Point {
+                    coordinates: std::array::from_fn(|i| coordinate(bits[i])),
+                }
+            }
+        }
+    }
+}
+
+/// The ChaCha12 stream for the batch of points starting at index `start`.
+fn batch_rng(seed: u64, start: usize) -> StdRng {
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&(start as u64).to_le_bytes());
+    StdRng::from_seed(key)
+}
+
+// The multipliers and key increments from Salmon et al., "Parallel
+// Random Numbers: As Easy as 1, 2, 3" (SC '11).
+const PHILOX_M0: u32 = 0xD251_1F53;
+const PHILOX_M1: u32 = 0xCD9E_8D57;
+const PHILOX_W0: u32 = 0x9E37_79B9;
+const PHILOX_W1: u32 = 0xBB67_AE85;
+const PHILOX_ROUNDS: usize = 10;
+
+/// The Philox4x32-10 counter-based generator. Rather than stepping
+/// through a sequence, it scrambles a 128-bit counter under a 64-bit key
+/// into 128 random bits, so any block can be had directly from its
+/// counter.
+#[derive(Debug, Clone, Copy)]
+pub struct Philox {
+    key: [u32; 2],
+}
+
+impl Philox {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            key: [seed as u32, (seed >> 32) as u32],
+        }
+    }
+
+    /// The 128 random bits for `counter`.
+    pub fn block(&self, counter: u128) -> [u32; 4] {
+        let mut block = [
+            counter as u32,
+            (counter >> 32) as u32,
+            (counter >> 64) as u32,
+            (counter >> 96) as u32,
+        ];
+        let mut key = self.key;
+        for round in 0..PHILOX_ROUNDS {
+            if round > 0 {
+                key[0] = key[0].wrapping_add(PHILOX_W0);
+                key[1] = key[1].wrapping_add(PHILOX_W1);
+            }
+            let (hi0, lo0) = multiply(PHILOX_M0, block[0]);
+            let (hi1, lo1) = multiply(PHILOX_M1, block[2]);
+            block = [hi1 ^ block[1] ^ key[0], lo1, hi0 ^ block[3] ^ key[1], lo0];
+        }
+        block
+    }
+
+    /// The point with the given index. Each block makes two coordinates,
+    /// one from each half, so a point in D dimensions takes D/2 blocks
+    /// (rounding up) with consecutive counters starting from the index
+    /// times that. In two dimensions the counter is just the index.
+    pub fn point<const D: usize>(&self, index: u64) -> Point<D> {
+        let blocks = D.div_ceil(2) as u128;
+        let join = |hi: u32, lo: u32| u64::from(hi) << 32 | u64::from(lo);
+        let mut coordinates = [0.0; D];
+        for (i, pair) in coordinates.chunks_mut(2).enumerate() {
+            let [a, b, c, d] = self.block(u128::from(index) * blocks + i as u128);
+            pair[0] = coordinate(join(a, b));
+            if let Some(second) = pair.get_mut(1) {
+                *second = coordinate(join(c, d));
+            }
+        }
+        Point { coordinates }
+    }
+}
+
+/// The coordinate for `bits` taken as a fraction of 2^64. The top 53
+/// bits make a float in [0, 1), which we stretch to [-1, 1) like
+/// `Point`'s `Distribution` does.
+fn coordinate(bits: u64) -> f64 {
+    2.0 * ((bits >> 11) as f64 / (1u64 << 53) as f64) - 1.0
+}
+
+/// The high and low halves of `a * b`.
+fn multiply(a: u32, b: u32) -> (u32, u32) {
+    let product = u64::from(a) * u64::from(b);
+    ((product >> 32) as u32, product as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The known-answer tests for Philox4x32-10 from Random123's
+    /// `kat_vectors`.
+    #[test]
+    fn philox_matches_the_known_answers() {
+        let cases = [
+            (0, 0, [0x6627_e8d5, 0xe169_c58d, 0xbc57_ac4c, 0x9b00_dbd8]),
+            (
+                u64::MAX,
+                u128::MAX,
+                [0x408f_276d, 0x41c8_3b0e, 0xa20b_c7c6, 0x6d54_51fd],
+            ),
+            (
+                0x299f_31d0_a409_3822,
+                0x0370_7344_1319_8a2e_85a3_08d3_243f_6a88,
+                [0xd16c_fe09, 0x94fd_cceb, 0x5001_e420, 0x2412_6ea1],
+            ),
+        ];
+        for (seed, counter, expected) in cases {
+            assert_eq!(Philox::new(seed).block(counter), expected);
+        }
+    }
+
+    #[test]
+    fn philox_points_in_more_dimensions_extend_those_in_two() {
+        let philox = Philox::new(3);
+        let flat: Point = philox.point(0);
+        let deep: Point<3> = philox.point(0);
+        assert_eq!(deep.coordinates[..2], flat.coordinates);
+        // A third coordinate takes a second block, so the next point
+        // starts two blocks on.
+        let next: Point<3> = philox.point(1);
+        let skipped: Point = philox.point(2);
+        assert_eq!(next.coordinates[..2], skipped.coordinates);
+    }
+}
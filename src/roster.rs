@@ -0,0 +1,70 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::sync::lock;
+
+/// Hands out clones of something (usually a channel sender) to stages
+/// that join during a run, and lets go of its own copy once the last of
+/// them has left.
+///
+/// Holding on to a sender so that stages added later can have a clone
+/// would otherwise keep its channel open forever, since a channel only
+/// closes once every sender is gone.
+#[derive(Debug)]
+pub struct Roster<T> {
+    state: Mutex<RosterState<T>>,
+}
+
+#[derive(Debug)]
+struct RosterState<T> {
+    template: Option<T>,
+    members: usize,
+}
+
+impl<T: Clone> Roster<T> {
+    pub fn new(template: T) -> Self {
+        Self {
+            state: Mutex::new(RosterState {
+                template: Some(template),
+                members: 0,
+            }),
+        }
+    }
+
+    /// Sign up a new stage, returning its membership (which it should
+    /// hold on to for as long as it's running) and its own clone of the
+    /// template, or `None` if the roster has been closed.
+    pub fn enlist(self: &Arc<Self>) -> Option<(Member<T>, T)> {
+        let mut state = self.state();
+        let template = state.template.clone()?;
+        state.members += 1;
+        Some((Member(Arc::clone(self)), template))
+    }
+}
+
+impl<T> Roster<T> {
+    /// Let go of the template now rather than when the last member
+    /// leaves, so no one else can join.
+    pub fn close(&self) {
+        self.state().template = None;
+    }
+
+    fn state(&self) -> MutexGuard<'_, RosterState<T>> {
+        lock(&self.state)
+    }
+}
+
+/// A stage's place on a [`Roster`]. Dropping it (however the stage
+/// finishes) takes the stage off the roster, closing it if it was the
+/// last one.
+#[derive(Debug)]
+pub struct Member<T>(Arc<Roster<T>>);
+
+impl<T> Drop for Member<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.state();
+        state.members -= 1;
+        if state.members == 0 {
+            state.template = None;
+        }
+    }
+}
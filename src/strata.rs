@@ -0,0 +1,251 @@
+use crate::{estimate_pi, point::Point};
+
+/// A grid of `side` × `side` equal cells ("strata") covering the square.
+/// In more than two dimensions the cells are slabs of the cube, split
+/// along the first two coordinates only.
+///
+/// Sampling the same number of points from every cell (stratified
+/// sampling) rather than from the whole square at once takes out the
+/// part of the error that comes from some regions getting more than
+/// their share of points. Cells entirely inside or outside the circle
+/// then contribute no error at all, leaving just the cells the edge of
+/// the circle passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Strata {
+    side: usize,
+}
+
+impl Strata {
+    pub fn new(side: usize) -> Self {
+        Self { side }
+    }
+
+    /// The number of cells.
+    pub fn len(&self) -> usize {
+        self.side * self.side
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.side == 0
+    }
+
+    /// Move `point`, which can be anywhere in the square, into the
+    /// `stratum`th cell, shrinking the square down to the cell so that
+    /// points spread evenly over the square are spread evenly over it.
+    pub fn place<const D: usize>(&self, mut point: Point<D>, stratum: usize) -> Point<D> {
+        let width = 2.0 / self.side as f64;
+        let shrink =
+            |cell: usize, coordinate: f64| -1.0 + width * (cell as f64 + (coordinate + 1.0) / 2.0);
+        point.coordinates[0] = shrink(stratum % self.side, point.coordinates[0]);
+        point.coordinates[1] = shrink(stratum / self.side, point.coordinates[1]);
+        point
+    }
+
+    /// The cell `point` is in.
+    pub fn stratum_of<const D: usize>(&self, point: &Point<D>) -> usize {
+        // Rounding can put a point right on the top or right edge of the
+        // square, which belongs with the cells just inside it.
+        let cell = |coordinate: f64| {
+            (((coordinate + 1.0) / 2.0 * self.side as f64) as usize).min(self.side - 1)
+        };
+        cell(point.coordinates[1]) * self.side + cell(point.coordinates[0])
+    }
+}
+
+/// Which cell each point of a run goes in: the run's points are split
+/// into consecutive runs of `per_stratum` points, one for each cell in
+/// turn.
+#[derive(Debug, Clone, Copy)]
+pub struct Allocation {
+    pub strata: Strata,
+    first_point: usize,
+    per_stratum: usize,
+}
+
+impl Allocation {
+    /// Share out `points` equally between the cells of `strata`, or say
+    /// why they can't be.
+    pub fn new(strata: Strata, first_point: usize, num_points: usize) -> Result<Self, String> {
+        if strata.is_empty() {
+            return Err("there must be at least one stratum".to_string());
+        }
+        if !num_points.is_multiple_of(strata.len()) {
+            return Err(format!(
+                "the number of points ({num_points}) must be a multiple of the number of strata ({})",
+                strata.len()
+            ));
+        }
+        let per_stratum = num_points / strata.len();
+        if per_stratum < 2 {
+            return Err(format!(
+                "each of the {} strata needs at least 2 points to estimate its variance",
+                strata.len()
+            ));
+        }
+        Ok(Self {
+            strata,
+            first_point,
+            per_stratum,
+        })
+    }
+
+    /// The largest number of whole cells' worth of points that's no more
+    /// than `max`, so that batches of this size each cover whole cells. If
+    /// one cell has more than `max` points, that's just `max`, and each
+    /// cell is spread over several batches instead. (Points are placed
+    /// by their index, so it doesn't matter which batch they're in.)
+    pub fn batch_size(&self, max: usize) -> usize {
+        if self.per_stratum > max {
+            max
+        } else {
+            self.per_stratum * (max / self.per_stratum)
+        }
+    }
+
+    /// Move `point` into the cell for the point with the given index.
+    pub fn place<const D: usize>(&self, point: Point<D>, index: usize) -> Point<D> {
+        let stratum = (index - self.first_point) / self.per_stratum;
+        self.strata.place(point, stratum)
+    }
+}
+
+/// How many points landed in each cell, and how many of those were
+/// inside the circle.
+#[derive(Debug, Clone)]
+pub struct StrataTally {
+    strata: Strata,
+    inside: Vec<usize>,
+    total: Vec<usize>,
+}
+
+impl StrataTally {
+    pub fn new(strata: Strata) -> Self {
+        Self {
+            strata,
+            inside: vec![0; strata.len()],
+            total: vec![0; strata.len()],
+        }
+    }
+
+    pub fn record<const D: usize>(&mut self, point: &Point<D>, inside: bool) {
+        let stratum = self.strata.stratum_of(point);
+        self.inside[stratum] += usize::from(inside);
+        self.total[stratum] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (ours, theirs) in self.inside.iter_mut().zip(&other.inside) {
+            *ours += theirs;
+        }
+        for (ours, theirs) in self.total.iter_mut().zip(&other.total) {
+            *ours += theirs;
+        }
+    }
+
+    /// The stratified estimate of π from points in `dimensions`
+    /// dimensions and its standard error, or `None` if any cell has fewer
+    /// than two points (as it will if the run was stopped early).
+    ///
+    /// Each cell is 1/C of the square, so the fraction of the square
+    /// inside the circle is 1/C times the sum of the fractions of each
+    /// cell's points that are inside. Its variance is 1/C² times the sum
+    /// of each cell's sample variance divided by its number of points.
+    pub fn estimate(&self, dimensions: usize) -> Option<(f64, f64)> {
+        if self.total.iter().any(|&n| n < 2) {
+            return None;
+        }
+        let weight = 1.0 / self.strata.len() as f64;
+        let (mut fraction, mut variance) = (0.0, 0.0);
+        for (&inside, &total) in self.inside.iter().zip(&self.total) {
+            let n = total as f64;
+            let p = inside as f64 / n;
+            fraction += weight * p;
+            variance += weight * weight * p * (1.0 - p) / (n - 1.0);
+        }
+        Some(estimate_pi(fraction, variance.sqrt(), dimensions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two points in each quadrant: half of those in the bottom left
+    /// inside, all of those in the bottom right and top left, and none of
+    /// those in the top right.
+    const POINTS: [[f64; 2]; 8] = [
+        [-0.1, -0.1],
+        [-0.9, -0.9],
+        [0.1, -0.1],
+        [0.2, -0.2],
+        [-0.1, 0.1],
+        [-0.2, 0.2],
+        [0.9, 0.9],
+        [0.8, 0.8],
+    ];
+
+    fn tally(points: &[[f64; 2]]) -> StrataTally {
+        let mut tally = StrataTally::new(Strata::new(2));
+        for &[x, y] in points {
+            let point = Point::new(x, y);
+            tally.record(&point, point.inside_unit_ball());
+        }
+        tally
+    }
+
+    #[test]
+    fn the_estimate_weights_each_cell_equally() {
+        // The cells' fractions inside average (1/2 + 1 + 1 + 0) / 4 = 5/8,
+        // and only the bottom left varies, with variance (1/2)(1/2)/(2 - 1)
+        // weighted by (1/4)², so the fraction's standard error is 1/8.
+        let (estimate, std_error) = tally(&POINTS).estimate(2).unwrap();
+        assert!((estimate - 2.5).abs() < 1e-12, "{estimate}");
+        assert!((std_error - 0.5).abs() < 1e-12, "{std_error}");
+    }
+
+    #[test]
+    fn merged_tallies_give_the_same_estimate() {
+        let mut first = tally(&POINTS[..3]);
+        first.merge(&tally(&POINTS[3..]));
+        assert_eq!(first.estimate(2), tally(&POINTS).estimate(2));
+    }
+
+    #[test]
+    fn cells_with_fewer_than_two_points_give_no_estimate() {
+        let mut tally = StrataTally::new(Strata::new(2));
+        tally.record(&Point::new(0.5, 0.5), true);
+        assert_eq!(tally.estimate(2), None);
+    }
+
+    #[test]
+    fn batches_cover_whole_cells_but_no_more_than_the_max() {
+        let allocation = Allocation::new(Strata::new(2), 0, 4 * 30).unwrap();
+        assert_eq!(allocation.batch_size(100), 90);
+        assert_eq!(allocation.batch_size(30), 30);
+        assert_eq!(allocation.batch_size(20), 20);
+    }
+
+    #[test]
+    fn cells_bigger_than_a_batch_are_spread_over_several() {
+        let strata = Strata::new(2);
+        let allocation = Allocation::new(strata, 1_000, 4 * 30).unwrap();
+        let batch_size = allocation.batch_size(20);
+        // The second batch finishes the first cell and starts the second.
+        let batch = 1_000 + batch_size..1_000 + 2 * batch_size;
+        let cells = batch
+            .map(|index| strata.stratum_of(&allocation.place(Point::new(0.0, 0.0), index)))
+            .collect::<Vec<_>>();
+        assert_eq!(cells, [[0; 10], [1; 10]].concat());
+    }
+
+    #[test]
+    fn placed_points_land_in_their_cells() {
+        let strata = Strata::new(3);
+        for stratum in 0..strata.len() {
+            for corner in [Point::new(-1.0, -1.0), Point::new(0.999, 0.999)] {
+                let placed = strata.place(corner, stratum);
+                assert_eq!(strata.stratum_of(&placed), stratum, "{placed:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    fmt::{self, Display},
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crossbeam_channel::Sender;
+
+use crate::error::{PipelineError, panic_message};
+
+/// A flag shared by all the stages of a pipeline that says "stop what
+/// you're doing, the run is over". Stages check it as they go and bail
+/// out with [`PipelineError::Cancelled`] once it's set.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err(Cancelled)` if the run has been cancelled, so stages can just
+    /// write `cancel.check(name)?` in their loops.
+    pub fn check(&self, stage: &str) -> Result<(), PipelineError> {
+        if self.is_cancelled() {
+            Err(PipelineError::Cancelled {
+                stage: stage.to_string(),
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// What a [`Supervisor`] should do when a stage it's running panics.
+///
+/// On the command line these are written as `never`, `always`, or a
+/// number of restarts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Treat the panic as a failure of the whole run.
+    Never,
+    /// Restart the stage up to this many times, after which the next
+    /// panic is a failure of the whole run.
+    Times(usize),
+    /// Restart the stage however many times it panics.
+    Always,
+}
+
+impl RestartPolicy {
+    /// Whether a stage that has already been restarted `restarts` times
+    /// can be restarted again.
+    pub fn allows_restart(self, restarts: usize) -> bool {
+        match self {
+            RestartPolicy::Never => false,
+            RestartPolicy::Times(n) => restarts < n,
+            RestartPolicy::Always => true,
+        }
+    }
+}
+
+impl FromStr for RestartPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(RestartPolicy::Never),
+            "always" => Ok(RestartPolicy::Always),
+            _ => s.parse::<usize>().map(RestartPolicy::Times).map_err(|_| {
+                format!("expected \"never\", \"always\", or a number of restarts, but got {s:?}")
+            }),
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestartPolicy::Never => write!(f, "never"),
+            RestartPolicy::Times(n) => write!(f, "{n}"),
+            RestartPolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+/// Runs stages so that if one of them fails (by panicking or returning
+/// an error that isn't just a knock-on effect of some other failure),
+/// the reporter hears about it right away and every other stage is told
+/// to stop.
+///
+/// Without this, a panicking manager would just quietly disappear, and
+/// the rest of the pipeline would carry on and report an estimate from
+/// partial data before `thread::scope` finally re-raised the panic.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    failure_sender: Sender<PipelineError>,
+    cancel: CancelToken,
+}
+
+impl Supervisor {
+    /// Failures are sent on `failure_sender`, which should go to the
+    /// reporter.
+    pub fn new(failure_sender: Sender<PipelineError>, cancel: CancelToken) -> Self {
+        Self {
+            failure_sender,
+            cancel,
+        }
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Run `stage`, catching any panic and turning it into a
+    /// [`PipelineError::WorkerPanicked`].
+    pub fn run<T>(
+        &self,
+        name: &str,
+        stage: impl FnOnce() -> Result<T, PipelineError>,
+    ) -> Result<T, PipelineError> {
+        let result = catch_panic(name, stage);
+        self.check(result)
+    }
+
+    /// Like [`Supervisor::run`], but if `stage` panics it's run again
+    /// (as long as `policy` allows it and the run hasn't been cancelled).
+    ///
+    /// The stage is restarted on the same thread: once the panic has
+    /// unwound, all that's left of the old attempt is whatever state
+    /// `stage` keeps in the things it has borrowed, which is exactly what
+    /// the new attempt needs to carry on where the old one left off. It's
+    /// up to the stage to make sure that state is consistent at any point
+    /// it might panic.
+    pub fn run_restarting<T>(
+        &self,
+        name: &str,
+        policy: RestartPolicy,
+        mut stage: impl FnMut() -> Result<T, PipelineError>,
+    ) -> Result<T, PipelineError> {
+        let mut restarts = 0;
+        loop {
+            let result = catch_panic(name, &mut stage);
+            if let Err(PipelineError::WorkerPanicked { message, .. }) = &result
+                && policy.allows_restart(restarts)
+                && !self.cancel.is_cancelled()
+            {
+                restarts += 1;
+                eprintln!("Restarting {name} (restart {restarts}) after it panicked: {message}");
+                continue;
+            }
+            return self.check(result);
+        }
+    }
+
+    /// Fail the run if `result` is an error that isn't just a knock-on
+    /// effect of some other failure.
+    fn check<T>(&self, result: Result<T, PipelineError>) -> Result<T, PipelineError> {
+        if let Err(e) = &result
+            && !e.is_knock_on()
+        {
+            self.fail(e.clone());
+        }
+        result
+    }
+
+    /// Report `error` to the reporter and cancel the run.
+    pub fn fail(&self, error: PipelineError) {
+        self.cancel.cancel();
+        // If the reporter is already gone there's no one left to tell.
+        let _ = self.failure_sender.send(error);
+    }
+}
+
+fn catch_panic<T>(
+    name: &str,
+    stage: impl FnOnce() -> Result<T, PipelineError>,
+) -> Result<T, PipelineError> {
+    panic::catch_unwind(AssertUnwindSafe(stage)).unwrap_or_else(|payload| {
+        Err(PipelineError::WorkerPanicked {
+            stage: name.to_string(),
+            message: panic_message(&*payload),
+        })
+    })
+}
@@ -0,0 +1,12 @@
+use std::sync::{Mutex, MutexGuard};
+
+/// Lock `mutex`, even if another thread panicked while holding it.
+///
+/// The locks here all guard plain lists and flags that are only changed
+/// by single pushes, pops, and assignments, so nothing panics partway
+/// through changing them, and if a thread somehow did panic while
+/// holding one the data would still be fine. Carrying on with it beats
+/// taking down every other stage along with the one that failed.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
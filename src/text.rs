@@ -0,0 +1,10 @@
+use std::fmt::{self, Write as _};
+
+/// Append `line` and a newline to `out`.
+///
+/// Writing to a `String` can't fail, so unlike `writeln!` this has no
+/// `Result` to ignore at every call site.
+pub fn write_line(out: &mut String, line: fmt::Arguments<'_>) {
+    let _ = out.write_fmt(line);
+    out.push('\n');
+}
@@ -0,0 +1,66 @@
+//! Export a timeline of what each stage was doing in the [Chrome Trace
+//! Event format], which can be loaded into `chrome://tracing` or
+//! <https://ui.perfetto.dev>.
+//!
+//! [Chrome Trace Event format]: https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::metrics::StageMetrics;
+
+/// A stretch of time a stage spent doing one thing (e.g., "generating a
+/// batch" or "blocked on send").
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub name: &'static str,
+    pub start: Instant,
+    pub duration: Duration,
+}
+
+/// Write the spans recorded by each of `stages` to `path`, with each stage
+/// as its own "thread" on the timeline. Times are relative to `epoch`,
+/// which should be some time before any of the stages started.
+pub fn write_chrome_trace<'a>(
+    path: &Path,
+    epoch: Instant,
+    stages: impl IntoIterator<Item = &'a StageMetrics>,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let micros = |d: Duration| d.as_secs_f64() * 1e6;
+
+    write!(out, "{{\"traceEvents\":[")?;
+    let mut first = true;
+    let mut separator = |out: &mut BufWriter<File>| {
+        let result = if first { Ok(()) } else { write!(out, ",") };
+        first = false;
+        result
+    };
+
+    for (tid, stage) in stages.into_iter().enumerate() {
+        separator(&mut out)?;
+        write!(
+            out,
+            "\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{tid},\"args\":{{\"name\":\"{}\"}}}}",
+            stage.name()
+        )?;
+        for span in stage.spans() {
+            separator(&mut out)?;
+            write!(
+                out,
+                "\n{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":1,\"tid\":{tid},\"ts\":{:.3},\"dur\":{:.3}}}",
+                span.name,
+                stage.kind(),
+                micros(span.start.saturating_duration_since(epoch)),
+                micros(span.duration),
+            )?;
+        }
+    }
+
+    writeln!(out, "\n]}}")?;
+    out.flush()
+}
@@ -0,0 +1,122 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender, bounded};
+
+use crate::{
+    channels::Link,
+    error::PipelineError,
+    metrics::{StageMetrics, StageRegistry, StageState},
+    text::write_line,
+};
+
+/// A stage that watches the rest of the pipeline for progress, and
+/// gives up on the run if none of the other stages has handled a message
+/// (or finished) for `timeout`. A paused stage doesn't count as stuck.
+///
+/// Forgetting to drop a sender somewhere is all it takes for a pipeline
+/// to hang forever, with every stage quietly waiting on a channel that
+/// will never close. The watchdog turns that into an error along with a
+/// report of where each stage is stuck.
+pub struct Watchdog {
+    stages: StageRegistry,
+    links: Vec<Link>,
+    timeout: Duration,
+    stop_receiver: Receiver<()>,
+}
+
+/// How many times per `timeout` the watchdog checks for progress.
+const CHECKS_PER_TIMEOUT: u32 = 10;
+
+impl Watchdog {
+    /// As with the [`crate::monitor::Monitor`], the watchdog keeps
+    /// watching until the returned sender is dropped.
+    pub fn new(stages: StageRegistry, links: Vec<Link>, timeout: Duration) -> (Self, Sender<()>) {
+        let (stop_sender, stop_receiver) = bounded(0);
+        let watchdog = Self {
+            stages,
+            links,
+            timeout,
+            stop_receiver,
+        };
+        (watchdog, stop_sender)
+    }
+
+    /// Watch until told to stop, returning `Err(Stalled)` (after printing
+    /// a report of every stage's state to stderr) if progress stops.
+    pub fn run(self) -> Result<(), PipelineError> {
+        let mut last_progress = progress(&self.stages.snapshot());
+        let mut last_progress_at = Instant::now();
+        loop {
+            match self
+                .stop_receiver
+                .recv_timeout(self.timeout / CHECKS_PER_TIMEOUT)
+            {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            let stages = self.stages.snapshot();
+            let progress = progress(&stages);
+            let paused = stages.iter().any(|s| s.state() == StageState::Paused);
+            if progress != last_progress || paused {
+                last_progress = progress;
+                last_progress_at = Instant::now();
+            } else if last_progress_at.elapsed() >= self.timeout {
+                eprintln!("The pipeline has stalled. Here's what each stage is doing:");
+                eprint!("{}", state_report(&stages, &self.links));
+                return Err(PipelineError::Stalled {
+                    idle: last_progress_at.elapsed(),
+                });
+            }
+        }
+    }
+}
+
+/// Something that changes whenever any stage makes progress.
+fn progress(stages: &[Arc<StageMetrics>]) -> usize {
+    stages
+        .iter()
+        .map(|s| s.messages() + usize::from(s.state() == StageState::Finished))
+        .sum()
+}
+
+/// A table with one row per stage showing what it's doing right now, and
+/// for stages blocked on a channel, how full that channel is.
+pub fn state_report(stages: &[Arc<StageMetrics>], links: &[Link]) -> String {
+    let occupancy = |channel: Option<&str>| {
+        let Some(channel) = channel else {
+            return "a channel".to_string();
+        };
+        match links.iter().find(|link| link.name() == channel) {
+            Some(link) => {
+                let capacity = link
+                    .capacity()
+                    .map_or_else(|| "unbounded".to_string(), |c| c.to_string());
+                format!("{channel} ({} queued, capacity {capacity})", link.len())
+            }
+            None => channel.to_string(),
+        }
+    };
+
+    let mut report = format!("{:<16} {:>10}  {}\n", "stage", "messages", "state");
+    for stage in stages {
+        let state = match stage.state() {
+            StageState::NotStarted => "not started".to_string(),
+            StageState::Working => "working".to_string(),
+            StageState::Sending => format!("blocked sending to {}", occupancy(stage.output())),
+            StageState::Receiving => {
+                format!("blocked receiving from {}", occupancy(stage.input()))
+            }
+            StageState::Paused => "paused".to_string(),
+            StageState::Finished => "finished".to_string(),
+        };
+        write_line(
+            &mut report,
+            format_args!("{:<16} {:>10}  {state}", stage.name(), stage.messages()),
+        );
+    }
+    report
+}
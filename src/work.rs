@@ -0,0 +1,56 @@
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// The points still to be generated in a run, which generators take a
+/// batch at a time. Each point has an index, so a batch is a range of
+/// indices.
+///
+/// Sharing out the work this way, rather than giving each generator a
+/// fixed share up front, means generators can come and go during a run
+/// without any points being skipped or generated twice.
+#[derive(Debug)]
+pub struct WorkPool {
+    next: AtomicUsize,
+    end: AtomicUsize,
+    /// The end of the furthest batch handed out so far.
+    taken: AtomicUsize,
+}
+
+impl WorkPool {
+    pub fn new(points: Range<usize>) -> Self {
+        Self {
+            next: AtomicUsize::new(points.start),
+            end: AtomicUsize::new(points.end),
+            taken: AtomicUsize::new(points.start),
+        }
+    }
+
+    /// Take the next batch of up to `max` points, or `None` if they've
+    /// all been taken.
+    pub fn take(&self, max: usize) -> Option<Range<usize>> {
+        let end = self.end.load(Ordering::Relaxed);
+        let start = self.next.fetch_add(max, Ordering::Relaxed);
+        let batch = (start < end).then(|| start..(start + max).min(end))?;
+        self.taken.fetch_max(batch.end, Ordering::Relaxed);
+        Some(batch)
+    }
+
+    /// The index of the next point to be handed out. Every point in a
+    /// batch that has already been taken has a lower index.
+    pub fn position(&self) -> usize {
+        // This can't come from `next` and `end`: a `take` that read `end`
+        // just before a `stop` still hands out its batch, past where
+        // `stop` put the end.
+        self.taken.load(Ordering::Relaxed)
+    }
+
+    /// Stop handing out work, so the run finishes early once the batches
+    /// that have already been taken are done (including any being taken
+    /// right now, which [`WorkPool::position`] accounts for).
+    pub fn stop(&self) {
+        self.end
+            .fetch_min(self.next.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,107 @@
+//! Helpers for running `crossbeam_mg_mc` from the integration tests.
+//!
+//! Each test file uses its own selection of these, so the rest look
+//! unused from any one of them.
+#![allow(dead_code)]
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Output, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+/// Far longer than any of these runs should take, even in a debug build.
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub const NUM_POINTS: &str = "100000";
+
+pub struct Run {
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub fn start_pipeline(num_points: &str, args: &[&str]) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_crossbeam_mg_mc"))
+        .args(["-n", num_points, "--display", "plain"])
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start crossbeam_mg_mc")
+}
+
+pub fn run_pipeline(args: &[&str]) -> Run {
+    wait_for(start_pipeline(NUM_POINTS, args), args)
+}
+
+pub fn wait_for(child: Child, args: &[&str]) -> Run {
+    // Wait on another thread so that a hung pipeline fails the test
+    // instead of hanging it.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(child.wait_with_output()));
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = receiver
+        .recv_timeout(TIMEOUT)
+        .unwrap_or_else(|_| panic!("pipeline hung with {args:?}"))
+        .expect("failed to wait for crossbeam_mg_mc");
+
+    Run {
+        code: status.code(),
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+    }
+}
+
+pub fn assert_completes(args: &[&str]) {
+    let run = run_pipeline(args);
+    assert_eq!(run.code, Some(0), "{args:?} failed:\n{}", run.stderr);
+    assert!(
+        run.stdout
+            .contains(&format!("Final estimate after {NUM_POINTS} points")),
+        "{args:?} didn't count every point:\n{}",
+        run.stdout
+    );
+}
+
+pub fn assert_fails_with(args: &[&str], code: i32, error: &str) {
+    let run = run_pipeline(args);
+    assert_eq!(run.code, Some(code), "{args:?}:\n{}", run.stderr);
+    assert!(
+        run.stderr.contains(&format!("Error: {error}")),
+        "{args:?} didn't fail with {error:?}:\n{}",
+        run.stderr
+    );
+}
+
+/// A path in the temporary directory that no other test (or other run of
+/// the tests) uses.
+pub fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("crossbeam_mg_mc-{}-{name}", process::id()))
+}
+
+/// Run the pipeline with `args`, saving its results to `path`.
+pub fn save_results(path: &Path, args: &[&str]) {
+    let args = [&["--save", path.to_str().unwrap()], args].concat();
+    assert_completes(&args);
+}
+
+/// Merge the results saved in `files`, passing `merge` any other `args`.
+pub fn merge(args: &[&str], files: &[&Path]) -> Run {
+    let child = Command::new(env!("CARGO_BIN_EXE_crossbeam_mg_mc"))
+        .arg("merge")
+        .args(args)
+        .args(files)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start crossbeam_mg_mc merge");
+    wait_for(child, &["merge"])
+}
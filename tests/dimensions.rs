@@ -0,0 +1,54 @@
+//! Runs `crossbeam_mg_mc` with points in more than two dimensions
+//! (`--dimensions`).
+
+mod common;
+
+use std::fs;
+
+use common::{assert_fails_with, run_pipeline, temp_path};
+
+#[test]
+fn runs_in_more_dimensions_estimate_the_volume_of_the_ball() {
+    let run = run_pipeline(&["--dimensions", "3", "-g", "2", "-m", "2"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(
+        run.stdout.contains("for the volume of the unit 3-ball"),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn quasi_random_points_in_more_dimensions_are_a_config_error() {
+    assert_fails_with(
+        &["--dimensions", "3", "--rng", "sobol"],
+        2,
+        "invalid configuration: sobol points only come in two dimensions, not 3",
+    );
+}
+
+#[test]
+fn too_many_dimensions_are_a_config_error() {
+    assert_fails_with(
+        &["--dimensions", "11"],
+        2,
+        "invalid configuration: points can have from 2 to 10 dimensions, not 11",
+    );
+}
+
+#[test]
+fn resuming_in_other_dimensions_is_a_config_error() {
+    let checkpoint = temp_path("dimensions.checkpoint");
+    fs::write(
+        &checkpoint,
+        "requested_points=100000\ntotal_points=0\ninside_points=0\nfirst_point=0\nposition=0\ndimensions=4\n",
+    )
+    .unwrap();
+    let path = checkpoint.to_str().unwrap();
+    assert_fails_with(
+        &["--resume", path, "--dimensions", "3"],
+        2,
+        "invalid configuration: the checkpoint is for a run in 4 dimensions, not 3",
+    );
+    fs::remove_file(&checkpoint).unwrap();
+}
@@ -0,0 +1,263 @@
+//! Runs `crossbeam_mg_mc` with faults injected into its stages (or
+//! interrupted, or resumed), checking that every run either finishes
+//! with the right number of points or fails with the error we'd expect,
+//! and never hangs.
+
+mod common;
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader},
+    process::Command,
+    thread,
+};
+
+use common::{
+    assert_completes, assert_fails_with, run_pipeline, start_pipeline, temp_path, wait_for,
+};
+
+#[test]
+fn completes_without_faults() {
+    assert_completes(&["-g", "2", "-m", "2"]);
+}
+
+#[test]
+fn completes_with_a_tree_of_aggregators() {
+    assert_completes(&["-g", "2", "-m", "8", "-f", "2"]);
+}
+
+#[test]
+fn completes_with_the_autoscaler() {
+    assert_completes(&[
+        "-g",
+        "2",
+        "--channel-capacity",
+        "100",
+        "--autoscale",
+        "--max-managers",
+        "4",
+    ]);
+}
+
+#[test]
+fn completes_despite_stalls() {
+    assert_completes(&[
+        "-m",
+        "2",
+        "--channel-capacity",
+        "100",
+        "--inject",
+        "generator0:stall=200ms@1000",
+        "--inject",
+        "manager1:stall=200ms@1000",
+    ]);
+}
+
+// Faults that only happen after some number of messages are injected into
+// every stage of a kind, since there's no telling how the points will be
+// shared out between them.
+
+#[test]
+fn restarted_managers_keep_their_counts() {
+    assert_completes(&[
+        "-m",
+        "2",
+        "--inject",
+        "manager:panic@1000",
+        "--inject",
+        "manager:panic@2000",
+        "--restart",
+        "2",
+    ]);
+}
+
+#[test]
+fn manager_panic_fails_the_run() {
+    let run = run_pipeline(&["-m", "2", "--inject", "manager:panic@1000"]);
+    assert_eq!(run.code, Some(1));
+    assert!(run.stdout.contains("Run invalid"), "{}", run.stdout);
+    assert!(!run.stdout.contains("Final estimate"), "{}", run.stdout);
+    assert!(
+        run.stderr
+            .contains("panicked: injected panic after 1000 messages"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn manager_panics_past_the_restart_limit_fail_the_run() {
+    let run = run_pipeline(&[
+        "-m",
+        "2",
+        "--inject",
+        "manager:panic@1000",
+        "--inject",
+        "manager:panic@2000",
+        "--restart",
+        "1",
+    ]);
+    assert_eq!(run.code, Some(1));
+    assert!(
+        run.stderr
+            .contains("panicked: injected panic after 2000 messages"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn generator_panic_fails_the_run() {
+    let run = run_pipeline(&["-g", "2", "--inject", "generator:panic@10"]);
+    assert_eq!(run.code, Some(1));
+    assert!(
+        run.stderr
+            .contains("panicked: injected panic after 10 messages"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn aggregator_panic_fails_the_run() {
+    assert_fails_with(
+        &["-m", "4", "-f", "2", "--inject", "aggregator:panic"],
+        1,
+        "aggregator",
+    );
+}
+
+#[test]
+fn reporter_panic_fails_the_run() {
+    assert_fails_with(&["--inject", "reporter:panic@2"], 1, "reporter panicked");
+}
+
+#[test]
+fn dropped_points_are_caught_by_accounting() {
+    assert_fails_with(
+        &["--inject", "manager:drop=10@100"],
+        1,
+        "the generators sent 100000 points but the reporter counted 99990",
+    );
+}
+
+#[test]
+fn dropped_reports_are_caught_by_accounting() {
+    assert_fails_with(
+        &["-m", "4", "-f", "2", "--inject", "aggregator:drop"],
+        1,
+        "the generators sent 100000 points but the reporter counted",
+    );
+}
+
+#[test]
+fn generator_closing_early_is_caught_by_accounting() {
+    assert_fails_with(
+        &["-g", "2", "--inject", "generator:close@10"],
+        1,
+        "the generators sent 2000 points but the reporter counted 20",
+    );
+}
+
+#[test]
+fn manager_closing_before_taking_any_points_loses_nothing() {
+    assert_completes(&["-m", "2", "--inject", "manager0:close"]);
+}
+
+#[test]
+fn last_manager_closing_early_does_not_hang_the_generators() {
+    assert_fails_with(
+        &["--channel-capacity", "10", "--inject", "manager0:close@100"],
+        1,
+        "generator 0 couldn't send on the points channel",
+    );
+}
+
+#[test]
+fn injecting_into_a_missing_stage_is_a_config_error() {
+    assert_fails_with(
+        &["-m", "2", "--inject", "manager2:panic"],
+        2,
+        "invalid configuration",
+    );
+}
+
+#[test]
+fn stalled_pipeline_is_caught_by_the_watchdog() {
+    let run = run_pipeline(&[
+        "--channel-capacity",
+        "100",
+        "--watchdog-ms",
+        "300",
+        "--inject",
+        "manager:stall=10000ms@1000",
+    ]);
+    assert_eq!(run.code, Some(1));
+    assert!(
+        run.stderr
+            .contains("blocked sending to points (100 queued, capacity 100)"),
+        "{}",
+        run.stderr
+    );
+    assert!(
+        run.stderr
+            .contains("Error: no stage has made any progress for"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn interrupting_finishes_with_a_partial_estimate() {
+    let mut child = start_pipeline("1000000000", &[]);
+
+    // Wait for the first update, so we know the run is underway (and the
+    // handler is installed) before interrupting it.
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut first_update = String::new();
+    stdout
+        .read_line(&mut first_update)
+        .expect("failed to read from crossbeam_mg_mc");
+    let interrupted = Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .expect("failed to run kill");
+    assert!(interrupted.success());
+
+    // We've taken stdout, so we read the rest of it ourselves.
+    let rest = thread::spawn(move || io::read_to_string(stdout));
+    let run = wait_for(child, &["(interrupted)"]);
+    let rest = rest
+        .join()
+        .unwrap()
+        .expect("failed to read from crossbeam_mg_mc");
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(rest.contains("Partial estimate after"), "{rest}");
+}
+
+#[test]
+fn resuming_counts_only_the_remaining_points() {
+    let checkpoint = temp_path("resume.checkpoint");
+    fs::write(
+        &checkpoint,
+        "requested_points=100000\ntotal_points=40000\ninside_points=31400\nfirst_point=0\nposition=41000\nseed=1\n",
+    )
+    .unwrap();
+    let path = checkpoint.to_str().unwrap();
+    assert_completes(&["--resume", path, "--checkpoint", path]);
+
+    let saved = fs::read_to_string(&checkpoint).unwrap();
+    fs::remove_file(&checkpoint).unwrap();
+    assert!(saved.contains("total_points=100000\n"), "{saved}");
+    assert!(saved.contains("position=101000\n"), "{saved}");
+    assert!(saved.contains("seed=1\n"), "{saved}");
+}
+
+#[test]
+fn resuming_from_a_bad_checkpoint_is_a_config_error() {
+    assert_fails_with(
+        &["--resume", "/nonexistent/checkpoint"],
+        2,
+        "invalid configuration: couldn't read checkpoint",
+    );
+}
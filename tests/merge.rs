@@ -0,0 +1,85 @@
+//! Saves the results of separate runs of `crossbeam_mg_mc` and combines
+//! them with its `merge` subcommand.
+
+mod common;
+
+use std::fs;
+
+use common::{NUM_POINTS, merge, save_results, temp_path};
+
+#[test]
+fn merging_rejects_overlapping_seeded_runs() {
+    let (first, second) = (temp_path("first.results"), temp_path("second.results"));
+    save_results(&first, &["--seed", "9", "--first-point", "0"]);
+    save_results(&second, &["--seed", "9", "--first-point", NUM_POINTS]);
+
+    let merged = merge(&[], &[&first, &second]);
+    let overlapping = merge(&[], &[&first, &second, &first]);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+
+    assert_eq!(merged.code, Some(0), "{}", merged.stderr);
+    assert!(
+        merged
+            .stdout
+            .contains("Combined estimate from 2 runs after 200000 points"),
+        "{}",
+        merged.stdout
+    );
+    assert_eq!(overlapping.code, Some(2), "{}", overlapping.stderr);
+    assert!(
+        overlapping
+            .stderr
+            .contains("both used points 0..100000 from seed 9"),
+        "{}",
+        overlapping.stderr
+    );
+}
+
+#[test]
+fn merging_rejects_runs_sharing_generator_streams() {
+    let (first, second) = (temp_path("first.streams"), temp_path("second.streams"));
+    let xoshiro = [
+        "--rng",
+        "xoshiro",
+        "--seed",
+        "5",
+        "-g",
+        "3",
+        "--first-stream",
+    ];
+    save_results(&first, &[&xoshiro[..], &["0"]].concat());
+    save_results(&second, &[&xoshiro[..], &["2"]].concat());
+
+    let merged = merge(&[], &[&first, &second]);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+
+    assert_eq!(merged.code, Some(2), "{}", merged.stderr);
+    assert!(
+        merged.stderr.contains("both used streams 2..3 from seed 5"),
+        "{}",
+        merged.stderr
+    );
+}
+
+#[test]
+fn merging_in_json_writes_a_single_record() {
+    let (first, second) = (temp_path("first.json"), temp_path("second.json"));
+    save_results(&first, &["--seed", "4", "--first-point", "0"]);
+    save_results(&second, &["--seed", "4", "--first-point", NUM_POINTS]);
+
+    let merged = merge(&["--format", "json"], &[&first, &second]);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+
+    assert_eq!(merged.code, Some(0), "{}", merged.stderr);
+    let lines = merged.stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "{}", merged.stdout);
+    assert!(
+        lines[0].starts_with(r#"{"kind":"merged","#)
+            && lines[0].contains(r#""total_points":200000,"#),
+        "{}",
+        merged.stdout
+    );
+}
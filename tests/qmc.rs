@@ -0,0 +1,50 @@
+//! Runs `crossbeam_mg_mc` with quasi-random points and `--replicates`.
+
+mod common;
+
+use common::{NUM_POINTS, run_pipeline};
+
+#[test]
+fn replicates_of_a_quasi_random_run_give_a_combined_estimate() {
+    let run = run_pipeline(&["--rng", "sobol", "--seed", "1", "--replicates", "3"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(
+        run.stdout.contains(&format!(
+            "Estimate from 3 replicates of {NUM_POINTS} points each"
+        )),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn replicates_write_one_csv_header_and_a_combined_record() {
+    let run = run_pipeline(&[
+        "--rng",
+        "r2",
+        "--seed",
+        "1",
+        "--replicates",
+        "3",
+        "--format",
+        "csv",
+    ]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let lines = run.stdout.lines().collect::<Vec<_>>();
+    assert_eq!(
+        lines
+            .iter()
+            .filter(|line| line.starts_with("kind,"))
+            .count(),
+        1,
+        "{}",
+        run.stdout
+    );
+    assert!(lines[0].starts_with("kind,"), "{}", run.stdout);
+    let combined = lines.last().unwrap();
+    assert!(
+        combined.starts_with("replicates,") && combined.contains(",300000,"),
+        "{}",
+        run.stdout
+    );
+}
@@ -0,0 +1,33 @@
+//! Checks how `crossbeam_mg_mc`'s choice of `--rng` affects which points
+//! a seeded run counts.
+
+mod common;
+
+use std::fs;
+
+use common::{NUM_POINTS, start_pipeline, temp_path, wait_for};
+
+#[test]
+fn counter_based_points_do_not_depend_on_how_the_work_is_split() {
+    let results = temp_path("split.results");
+    let path = results.to_str().unwrap();
+    let inside_points = |num_points: &str, args: &[&str]| {
+        let args = [&["--rng", "philox", "--seed", "3", "--save", path], args].concat();
+        let run = wait_for(start_pipeline(num_points, &args), &args);
+        assert_eq!(run.code, Some(0), "{args:?} failed:\n{}", run.stderr);
+        let saved = fs::read_to_string(&results).unwrap();
+        saved
+            .lines()
+            .find_map(|line| line.strip_prefix("inside_points="))
+            .unwrap_or_else(|| panic!("no inside_points in {saved}"))
+            .parse::<usize>()
+            .unwrap()
+    };
+
+    let whole = inside_points(NUM_POINTS, &[]);
+    let threaded = inside_points(NUM_POINTS, &["-g", "3", "-m", "2", "-f", "2"]);
+    let split = inside_points("12345", &[]) + inside_points("87655", &["--first-point", "12345"]);
+    fs::remove_file(&results).unwrap();
+    assert_eq!(threaded, whole);
+    assert_eq!(split, whole);
+}
@@ -0,0 +1,45 @@
+//! Runs `crossbeam_mg_mc` with stratified sampling (`--strata`).
+
+mod common;
+
+use common::{assert_fails_with, run_pipeline};
+
+#[test]
+fn stratified_runs_report_a_stratified_estimate() {
+    let run = run_pipeline(&["--strata", "10", "-g", "2", "-m", "2"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    assert!(
+        run.stdout.contains("Stratified estimate from 100 strata"),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn strata_that_do_not_divide_the_points_are_a_config_error() {
+    assert_fails_with(
+        &["--strata", "3"],
+        2,
+        "invalid configuration: the number of points (100000) must be a multiple of the number of strata (9)",
+    );
+}
+
+#[test]
+fn the_stratified_estimate_is_a_record_in_json() {
+    let run = run_pipeline(&["--strata", "10", "--format", "json"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    for line in run.stdout.lines() {
+        assert!(
+            line.starts_with('{') && line.ends_with('}'),
+            "not a JSON record: {line}"
+        );
+    }
+    assert!(
+        run.stdout
+            .lines()
+            .last()
+            .is_some_and(|line| line.starts_with(r#"{"kind":"stratified","#)),
+        "{}",
+        run.stdout
+    );
+}
@@ -0,0 +1,34 @@
+//! Checks that the generators' substreams of a seeded xoshiro stream
+//! don't overlap.
+//!
+//! They're 2^128 numbers apart, so they can't unless a generator uses up
+//! more than that, but the test makes sure each generator really does get
+//! a substream of its own.
+
+use std::collections::HashSet;
+
+use pi_estimation_channels::{point::Point, rng::PointRng};
+
+const NUM_GENERATORS: usize = 8;
+
+/// How many points to check from each generator's stream. Finding no
+/// repeats among this many doesn't prove anything on its own, but it
+/// would catch generators sharing a stream, or streams that start close
+/// together.
+const POINTS_PER_GENERATOR: usize = 100_000;
+
+#[test]
+fn generator_streams_do_not_overlap() {
+    let mut seen = HashSet::with_capacity(NUM_GENERATORS * POINTS_PER_GENERATOR);
+    for stream in 0..NUM_GENERATORS {
+        let mut points = PointRng::Xoshiro.stream(42, stream);
+        points.start_batch(0);
+        for index in 0..POINTS_PER_GENERATOR {
+            let point: Point = points.point(index);
+            assert!(
+                seen.insert(point.coordinates.map(f64::to_bits)),
+                "stream {stream} repeated a point from an earlier stream at its {index}th point"
+            );
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Runs `crossbeam_mg_mc` with the antithetic and control variate
+//! estimators (`--variance-reduction`).
+
+mod common;
+
+use common::run_pipeline;
+
+#[test]
+fn variance_reduction_reports_both_estimators() {
+    let run = run_pipeline(&["--variance-reduction", "-m", "2"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    for estimator in ["Antithetic", "Control variate"] {
+        assert!(
+            run.stdout.contains(&format!("{estimator} estimate is")),
+            "{}",
+            run.stdout
+        );
+    }
+}
+
+#[test]
+fn both_estimates_are_records_in_csv() {
+    let run = run_pipeline(&["--variance-reduction", "--format", "csv"]);
+    assert_eq!(run.code, Some(0), "{}", run.stderr);
+    let kinds = run
+        .stdout
+        .lines()
+        .map(|line| line.split(',').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds[kinds.len() - 3..],
+        ["summary", "antithetic", "control_variate"],
+        "{}",
+        run.stdout
+    );
+}